@@ -1,12 +1,36 @@
 use structopt::StructOpt;
 
-use archiveis::{ArchiveClient, Archived};
-use futures::{stream, StreamExt};
-use serde::{Deserialize, Serialize};
+use archiveis::{
+    bookmarks::{parse_bookmarks, parse_csv, BookmarkFormat, CsvColumns},
+    extract::{DocumentFormat, Extractor, Link},
+    feed::{Feed, FeedClient, FeedEntry},
+    filter::{LinkFilter, Regex},
+    normalize::Normalizer,
+    rewrite::{rewritable, rewrite, RewriteMode},
+    scan::{Scanned, Scanner},
+    sitemap::{Sitemap, SitemapClient, SitemapEntry},
+    store::{CaptureStore, FeedStore, Journal, Record, SeenEntry, SqliteStore, Status},
+    template::{Escape, Template},
+    ArchiveClient, Archived, LocalArchiver, MultiArchived, MultiArchiver, Policy, Provider,
+    SnapshotFormat, WaybackClient,
+};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures::{
+    channel::mpsc,
+    executor, future,
+    stream::{self, LocalBoxStream},
+    SinkExt, StreamExt,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
     fs,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+    thread,
 };
 use url::Url;
 
@@ -15,7 +39,15 @@ use url::Url;
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "archive",
-    about = "Archive urls using the archive.is capturing service."
+    about = "Archive urls using the archive.is capturing service.",
+    after_help = "EXIT CODES:
+    0    every link was archived, or --ignore-failures was set
+    1    some links could not be archived
+    2    no link could be archived
+    3    the input could not be read or contains invalid lines, the valid links were archived
+    4    no submit token could be obtained from archive.is
+    5    an archive service rate limited the requests or asked to solve a CAPTCHA
+    6    the output, failures file, journal or database could not be written"
 )]
 #[structopt(setting = structopt::clap::AppSettings::ColoredHelp)]
 enum App {
@@ -38,14 +70,350 @@ enum App {
         #[structopt(
             short = "i",
             parse(from_os_str),
-            help = "archive all the links in the line separated text file"
+            help = "archive all the links in the line separated text file, or of stdin if it is `-`"
+        )]
+        input: PathBuf,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(
+        name = "extract",
+        about = "Archive all the links in a html, Markdown or text document"
+    )]
+    Extract {
+        #[structopt(
+            short = "i",
+            parse(from_os_str),
+            help = "the document to archive the links of, or `-` to read it from stdin"
+        )]
+        input: PathBuf,
+        #[structopt(flatten)]
+        document: DocumentOpts,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(
+        name = "bookmarks",
+        about = "Archive the bookmarks exported by a browser or a link saving service"
+    )]
+    Bookmarks {
+        #[structopt(
+            short = "i",
+            parse(from_os_str),
+            help = "the bookmark export of a browser, Pocket, Pinboard or a CSV file, or `-` to read it from stdin"
+        )]
+        input: PathBuf,
+        #[structopt(
+            name = "input-format",
+            long = "input-format",
+            help = "the format of the export, guessed from its content by default  [possible values: netscape, firefox, chrome, pocket, pinboard, csv]"
+        )]
+        format: Option<BookmarkFormat>,
+        #[structopt(
+            long = "url-column",
+            default_value = "url",
+            help = "the name or number of the column of the urls in a CSV file"
+        )]
+        url_column: String,
+        #[structopt(
+            long = "title-column",
+            default_value = "title",
+            help = "the name or number of the column of the titles in a CSV file"
+        )]
+        title_column: String,
+        #[structopt(
+            long = "tags-column",
+            default_value = "tags",
+            help = "the name or number of the column of the comma separated tags in a CSV file"
+        )]
+        tags_column: String,
+        #[structopt(
+            long = "folder",
+            number_of_values = 1,
+            help = "only archive the bookmarks in this folder or its subfolders, like `Work/Rust`, can be given multiple times"
         )]
+        folders: Vec<String>,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(
+        name = "rewrite",
+        about = "Archive all the links in a html or Markdown document and add the archives to it"
+    )]
+    Rewrite {
+        #[structopt(parse(from_os_str), help = "the document to rewrite")]
         input: PathBuf,
+        #[structopt(
+            long = "mode",
+            default_value = "append",
+            possible_values = &["append", "replace"],
+            help = "whether to add a link to the archives next to each link or to replace the links"
+        )]
+        mode: RewriteMode,
+        #[structopt(
+            long = "rewrite-to",
+            parse(from_os_str),
+            help = "write the rewritten document to this file instead of overwriting the document"
+        )]
+        rewrite_to: Option<PathBuf>,
+        #[structopt(flatten)]
+        document: DocumentOpts,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(
+        name = "scan",
+        about = "Archive all the links in the files of a directory and report where they were found"
+    )]
+    Scan {
+        #[structopt(
+            parse(from_os_str),
+            help = "the directory to scan, files ignored by `.gitignore` are skipped"
+        )]
+        dir: PathBuf,
+        #[structopt(long = "hidden", help = "scan hidden files and directories as well")]
+        hidden: bool,
+        #[structopt(
+            long = "report",
+            parse(from_os_str),
+            help = "save the report of every link with the files and lines it was found in and its archives, printed if not set"
+        )]
+        report: Option<PathBuf>,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(
+        name = "sitemap",
+        about = "Archive all the pages of a sitemap or sitemap index"
+    )]
+    Sitemap {
+        #[structopt(help = "the url or path of the sitemap, which may be gzip compressed")]
+        sitemap: String,
+        #[structopt(
+            long = "since",
+            parse(try_from_str = parse_date),
+            help = "only archive pages modified at or after this date, like 2024-01-31"
+        )]
+        since: Option<DateTime<Utc>>,
+        #[structopt(
+            long = "changed",
+            requires = "db",
+            help = "only archive pages modified after their latest capture in the --db database"
+        )]
+        changed: bool,
+        #[structopt(flatten)]
+        opts: Opts,
+    },
+    #[structopt(name = "feed", about = "Archive the new entries of a RSS or Atom feed")]
+    Feed {
+        #[structopt(
+            requires = "db",
+            help = "the url or path of the feed, the entries already archived are remembered in the --db database"
+        )]
+        feed: String,
         #[structopt(flatten)]
         opts: Opts,
     },
 }
 
+/// a document whose links are rewritten to their archives after capturing them
+struct Rewrite {
+    /// where the rewritten document is written to
+    target: PathBuf,
+    document: String,
+    format: DocumentFormat,
+    links: Vec<Link>,
+    mode: RewriteMode,
+}
+
+impl Rewrite {
+    /// reads the document at `input` and the links in it that are rewritten, the rewritten
+    /// document is written to `target` or replaces `input`
+    fn read(
+        input: PathBuf,
+        target: Option<PathBuf>,
+        document: &DocumentOpts,
+        mode: RewriteMode,
+    ) -> Result<Self, Failure> {
+        let (content, format) = document.read(&input)?;
+        // archives of archives are pointless
+        let extractor = ARCHIVE_DOMAINS
+            .iter()
+            .fold(document.extractor(), |extractor, domain| {
+                extractor.exclude_domain(domain)
+            });
+        // stylesheets, scripts and images of the document stay as they are
+        let links = rewritable(&content, format, extractor.links(&content, format));
+        Ok(Rewrite {
+            target: target.unwrap_or(input),
+            document: content,
+            format,
+            links,
+            mode,
+        })
+    }
+
+    /// the urls of the rewritten links without duplicates
+    fn urls(&self) -> Vec<Url> {
+        let mut urls: Vec<Url> = Vec::new();
+        for link in &self.links {
+            if !urls.contains(&link.url) {
+                urls.push(link.url.clone());
+            }
+        }
+        urls
+    }
+
+    /// writes the document with the archives of `successes`
+    fn write(&self, archiver: &MultiArchiver, successes: &[Archived]) -> io::Result<()> {
+        let rewritten = rewrite(&self.document, self.format, &self.links, self.mode, |url| {
+            archives_of(archiver, successes, url)
+        });
+        fs::write(&self.target, rewritten)
+    }
+}
+
+/// the archives of `url` among `successes`, in the order the providers were selected
+fn archives_of(archiver: &MultiArchiver, successes: &[Archived], url: &Url) -> Vec<Archived> {
+    let providers = archiver.providers();
+    let target = archiver.target_url(url);
+    let mut archives: Vec<_> = successes
+        .iter()
+        .filter(|archived| archived.target_url == target.as_str())
+        .cloned()
+        .collect();
+    archives.sort_by_key(|archived| providers.iter().position(|p| *p == archived.provider));
+    archives
+}
+
+/// the links found by scanning a directory, reported with their archives after capturing them
+struct ScanReport {
+    /// where the report is written to, stdout if not set
+    target: Option<PathBuf>,
+    scanned: Vec<Scanned>,
+}
+
+/// a link of the scan report
+#[derive(Debug, Serialize)]
+struct ReportEntry {
+    url: String,
+    /// where the link was found, like `src/lib.rs:12`
+    locations: Vec<String>,
+    archives: Vec<Output>,
+}
+
+impl ScanReport {
+    /// scans the files in `dir`, and in hidden files and directories if `hidden` is set
+    fn scan(dir: &Path, hidden: bool, target: Option<PathBuf>) -> Result<Self, Failure> {
+        // archives of archives are pointless
+        let extractor = ARCHIVE_DOMAINS
+            .iter()
+            .fold(Extractor::new(), |extractor, domain| {
+                extractor.exclude_domain(domain)
+            });
+        let scanned = Scanner::new()
+            .extractor(extractor)
+            .hidden(hidden)
+            .scan(dir)
+            .map_err(fail(
+                Exit::BadInput,
+                format!("Cannot scan {}", dir.display()),
+            ))?;
+        Ok(ScanReport { target, scanned })
+    }
+
+    /// writes every link with its locations and archives, as json or as lines of
+    /// `file:line url archives...` separated by tabs
+    fn write(
+        &self,
+        archiver: &MultiArchiver,
+        successes: &[Archived],
+        text: bool,
+    ) -> io::Result<()> {
+        use ::std::io::prelude::*;
+        let mut out: Box<dyn Write> = match &self.target {
+            Some(target) => Box::new(fs::File::create(target)?),
+            None => Box::new(io::stdout()),
+        };
+        let entries = self.scanned.iter().map(|scanned| ReportEntry {
+            url: scanned.url.to_string(),
+            locations: scanned
+                .locations
+                .iter()
+                .map(|location| format!("{}:{}", location.path.display(), location.line))
+                .collect(),
+            archives: archives_of(archiver, successes, &scanned.url)
+                .into_iter()
+                .map(Output::from)
+                .collect(),
+        });
+        if text {
+            for entry in entries {
+                let archives: Vec<_> = entry.archives.iter().map(|a| a.archive.as_str()).collect();
+                for location in &entry.locations {
+                    writeln!(out, "{}\t{}\t{}", location, entry.url, archives.join("\t"))?;
+                }
+            }
+        } else {
+            let entries: Vec<_> = entries.collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&entries)?)?;
+        }
+        out.flush()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct DocumentOpts {
+    #[structopt(
+        name = "input-format",
+        long = "input-format",
+        possible_values = &["html", "markdown", "text"],
+        help = "the format of the document, guessed from its extension or content by default"
+    )]
+    format: Option<DocumentFormat>,
+    #[structopt(long = "base", help = "resolve relative links against this url")]
+    base: Option<Url>,
+    #[structopt(
+        long = "include-domain",
+        use_delimiter = true,
+        help = "only archive links to these domains and their subdomains, separated by comma"
+    )]
+    include_domains: Vec<String>,
+    #[structopt(
+        long = "exclude-domain",
+        use_delimiter = true,
+        help = "never archive links to these domains and their subdomains, separated by comma"
+    )]
+    exclude_domains: Vec<String>,
+}
+
+impl DocumentOpts {
+    /// the extractor for the links of the document
+    fn extractor(&self) -> Extractor {
+        let mut extractor = Extractor::new();
+        if let Some(base) = &self.base {
+            extractor = extractor.base(base.clone());
+        }
+        for domain in &self.include_domains {
+            extractor = extractor.include_domain(domain);
+        }
+        for domain in &self.exclude_domains {
+            extractor = extractor.exclude_domain(domain);
+        }
+        extractor
+    }
+
+    /// reads the document at `input`, or stdin if `input` is `-`, together with its format
+    fn read(&self, input: &Path) -> Result<(String, DocumentFormat), Failure> {
+        let document = read_input(input)?;
+        let format = self.format.unwrap_or_else(|| {
+            let path = Some(input).filter(|input| *input != Path::new("-"));
+            DocumentFormat::guess(path, &document)
+        });
+        Ok((document, format))
+    }
+}
+
 #[derive(Debug, StructOpt)]
 struct Opts {
     #[structopt(short = "o", parse(from_os_str), help = "save all archived elements")]
@@ -58,10 +426,32 @@ struct Opts {
         help = "save output as line separated text instead of json"
     )]
     text: bool,
+    #[structopt(
+        name = "format",
+        long = "format",
+        possible_values = &["json", "ndjson", "csv", "tsv", "markdown", "text"],
+        conflicts_with = "text",
+        help = "the format of the output file, guessed from its extension and json by default"
+    )]
+    output_format: Option<OutputFormat>,
+    #[structopt(
+        long = "template",
+        parse(try_from_str = parse_template),
+        conflicts_with_all = &["format", "text", "archives-only"],
+        help = "save every archived link as a line of this template, like `[{title}]({archive})`, or print the lines without output file; the fields are target, archive, provider, timestamp, token, title, folder and tags"
+    )]
+    template: Option<Template>,
+    #[structopt(
+        long = "escape",
+        possible_values = &["none", "markdown", "html", "shell"],
+        requires = "template",
+        help = "escape the values of the template fields for Markdown, html or the shell"
+    )]
+    escape: Option<Escape>,
     #[structopt(
         short = "a",
         long = "append",
-        help = "if the output file already exists, append instead of overwriting the file"
+        help = "if the output file already exists, append instead of overwriting the file, a json array is merged with the new links"
     )]
     append: bool,
     #[structopt(short = "s", long = "silent", help = "do not print anything")]
@@ -75,66 +465,693 @@ struct Opts {
     retries: usize,
     #[structopt(
         long = "ignore-failures",
-        help = "continue anyway if after all retries some links are not successfully archived"
+        help = "exit successfully even if after all retries some links are not successfully archived"
     )]
     ignore_failures: bool,
+    #[structopt(
+        short = "p",
+        long = "provider",
+        default_value = "archiveis",
+        use_delimiter = true,
+        possible_values = &["archiveis", "wayback", "local"],
+        help = "the archive services to capture the links with, separated by comma"
+    )]
+    providers: Vec<Provider>,
+    #[structopt(
+        long = "policy",
+        default_value = "all",
+        possible_values = &["all", "first-success", "fallback"],
+        help = "whether to capture with all providers, stop at the first success or try the providers in order until one succeeds"
+    )]
+    policy: Policy,
+    #[structopt(
+        long = "snapshot-dir",
+        parse(from_os_str),
+        default_value = "snapshots",
+        help = "where the local provider stores its snapshots"
+    )]
+    snapshot_dir: PathBuf,
+    #[structopt(
+        long = "mhtml",
+        help = "store local snapshots as MIME html archives instead of single html files"
+    )]
+    mhtml: bool,
+    #[structopt(
+        long = "db",
+        parse(from_os_str),
+        help = "record every capture in this SQLite database"
+    )]
+    db: Option<PathBuf>,
+    #[structopt(
+        long = "journal",
+        parse(from_os_str),
+        help = "write the outcome of every link to this file as soon as it is captured"
+    )]
+    journal: Option<PathBuf>,
+    #[structopt(
+        long = "resume",
+        requires = "journal",
+        help = "skip all links that were already archived according to the journal"
+    )]
+    resume: bool,
+    #[structopt(
+        long = "failures",
+        parse(from_os_str),
+        help = "save the links that could not be archived, which can be archived again with the `file` subcommand"
+    )]
+    failures: Option<PathBuf>,
+    #[structopt(
+        long = "auto-scheme",
+        help = "archive lines of the input file without a scheme, like `example.com`, via https"
+    )]
+    auto_scheme: bool,
+    #[structopt(
+        long = "no-normalize",
+        help = "capture the links as given, without removing fragments and tracking parameters"
+    )]
+    no_normalize: bool,
+    #[structopt(
+        long = "upgrade-scheme",
+        conflicts_with = "no-normalize",
+        help = "normalize http links to https"
+    )]
+    upgrade_scheme: bool,
+    #[structopt(
+        long = "strip-trailing-slash",
+        conflicts_with = "no-normalize",
+        help = "normalize links by removing the trailing slash of their path"
+    )]
+    strip_trailing_slash: bool,
+    #[structopt(
+        long = "tracking-param",
+        use_delimiter = true,
+        conflicts_with = "no-normalize",
+        help = "remove these query parameters as well when normalizing, like `ref` or `source_*`, separated by comma"
+    )]
+    tracking_params: Vec<String>,
+    #[structopt(
+        long = "include",
+        parse(try_from_str = Regex::new),
+        help = "only archive links matching one of these regular expressions"
+    )]
+    includes: Vec<Regex>,
+    #[structopt(
+        long = "exclude",
+        parse(try_from_str = Regex::new),
+        help = "never archive links matching one of these regular expressions"
+    )]
+    excludes: Vec<Regex>,
+    #[structopt(
+        long = "allow-domain",
+        use_delimiter = true,
+        help = "only archive links to these domains and their subdomains, separated by comma, `*` matches any part of a domain"
+    )]
+    allow_domains: Vec<String>,
+    #[structopt(
+        long = "deny-domain",
+        use_delimiter = true,
+        help = "never archive links to these domains and their subdomains, separated by comma, `*` matches any part of a domain"
+    )]
+    deny_domains: Vec<String>,
+    #[structopt(
+        long = "dry-run",
+        help = "only print which links would be captured, skipped or rejected, without submitting or writing anything"
+    )]
+    dry_run: bool,
+    #[structopt(
+        long = "lookup",
+        requires = "dry-run",
+        help = "look up the latest existing snapshot of every link that would be captured"
+    )]
+    lookup: bool,
+    #[structopt(
+        long = "reject-private",
+        help = "never archive links to localhost, private ip addresses and intranet hosts"
+    )]
+    reject_private: bool,
+}
+
+impl App {
+    /// the options shared by all subcommands
+    fn opts(&self) -> &Opts {
+        match self {
+            App::Links { opts, .. }
+            | App::File { opts, .. }
+            | App::Extract { opts, .. }
+            | App::Bookmarks { opts, .. }
+            | App::Rewrite { opts, .. }
+            | App::Scan { opts, .. }
+            | App::Sitemap { opts, .. }
+            | App::Feed { opts, .. } => opts,
+        }
+    }
 }
 
 impl Opts {
-    pub(crate) fn write_output(&self, archives: Vec<Output>) {
-        use ::std::io::prelude::*;
-        if let Some(out) = &self.output {
-            let mut file = if self.append && out.exists() {
-                fs::OpenOptions::new().write(true).append(true).open(out)
-            } else {
-                fs::File::create(out)
+    /// the clients for the selected archive services
+    fn archiver(&self) -> MultiArchiver {
+        let mut archiver = MultiArchiver::new(self.policy);
+        for provider in &self.providers {
+            match provider {
+                Provider::Wayback => archiver.push(Box::new(WaybackClient::default())),
+                Provider::Local => {
+                    let format = if self.mhtml {
+                        SnapshotFormat::Mhtml
+                    } else {
+                        SnapshotFormat::Html
+                    };
+                    archiver.push(Box::new(
+                        LocalArchiver::new(&self.snapshot_dir).format(format),
+                    ))
+                }
+                _ => archiver.push(Box::new(ArchiveClient::default())),
             }
-            .expect(&format!("Failed to open file {}", out.display()));
+        }
+        archiver = archiver.filter(self.filter());
+        if self.no_normalize {
+            return archiver;
+        }
+        let normalizer = self.tracking_params.iter().fold(
+            Normalizer::new()
+                .upgrade_scheme(self.upgrade_scheme)
+                .strip_trailing_slash(self.strip_trailing_slash),
+            |normalizer, param| normalizer.tracking_param(param),
+        );
+        archiver.normalize(normalizer)
+    }
 
-            let len = archives.len();
+    /// the filter for the links to archive
+    fn filter(&self) -> LinkFilter {
+        let mut filter = LinkFilter::new().reject_private(self.reject_private);
+        for regex in &self.includes {
+            filter = filter.include(regex.clone());
+        }
+        for regex in &self.excludes {
+            filter = filter.exclude(regex.clone());
+        }
+        for domain in &self.allow_domains {
+            filter = filter.allow_domain(domain);
+        }
+        for domain in &self.deny_domains {
+            filter = filter.deny_domain(domain);
+        }
+        filter
+    }
 
-            if self.text {
-                for archive in archives {
-                    let write = if self.archives_only {
-                        writeln!(file, "{}", archive.archive)
-                    } else {
-                        writeln!(file, "{}\t{}", archive.target, archive.archive)
-                    };
+    /// the journal of this run, if any
+    ///
+    /// A dry run only reads an existing journal to resume from.
+    fn journal(&self) -> archiveis::Result<Option<Journal>> {
+        match &self.journal {
+            Some(path) if self.dry_run => {
+                if self.resume && path.exists() {
+                    Journal::open(path).map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+            Some(path) if self.resume => Journal::open(path).map(Some),
+            Some(path) => Journal::create(path).map(Some),
+            None => Ok(None),
+        }
+    }
 
-                    if let Err(e) = write {
-                        if !self.silent {
-                            eprintln!("Couldn't write to file: {}", e);
-                        }
-                    }
+    /// the template of the output lines, with the requested escaping
+    fn template(&self) -> Option<Template> {
+        let escape = self.escape.unwrap_or_default();
+        self.template
+            .clone()
+            .map(|template| template.escape(escape))
+    }
+
+    /// the format of the output file: as requested, guessed from its extension or json
+    fn output_format(&self) -> OutputFormat {
+        if self.template.is_some() {
+            return OutputFormat::Template;
+        }
+        if self.text {
+            return OutputFormat::Text;
+        }
+        self.output_format
+            .or_else(|| self.output.as_deref().and_then(OutputFormat::guess))
+            .unwrap_or(OutputFormat::Json)
+    }
+
+    /// writes a line with the url, provider, error kind and message of every failed capture
+    fn write_failures(&self, failures: &[&MultiArchived]) -> std::io::Result<()> {
+        use ::std::io::prelude::*;
+        if let Some(out) = &self.failures {
+            let mut file = fs::File::create(out)?;
+            for capture in failures {
+                for (provider, err) in capture.failures() {
+                    writeln!(
+                        file,
+                        "{}\t{}\t{}\t{}",
+                        capture.target_url,
+                        provider,
+                        err.kind(),
+                        err.to_string().replace(['\t', '\n'], " ")
+                    )?;
                 }
+            }
+            if !self.silent {
+                println!(
+                    "Wrote {} failed links to: {}",
+                    failures.len(),
+                    out.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the formats the archived links can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// a pretty printed json array
+    Json,
+    /// a json object per line, written as soon as a link is captured
+    Ndjson,
+    /// comma separated values with a header line
+    Csv,
+    /// tab separated values with a header line
+    Tsv,
+    /// a Markdown table
+    Markdown,
+    /// the target and archive separated by a tab, without header
+    Text,
+    /// a line of the `--template` per link
+    Template,
+}
+
+impl OutputFormat {
+    /// guesses the format from the extension of the output file, if it is unambiguous
+    fn guess(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "text" | "txt" => Ok(OutputFormat::Text),
+            _ => Err(format!("Unknown output format {}", s)),
+        }
+    }
+}
+
+/// the fields of the outputs that can be used in a template
+const TEMPLATE_FIELDS: &[&str] = &[
+    "target",
+    "archive",
+    "provider",
+    "timestamp",
+    "token",
+    "title",
+    "folder",
+    "tags",
+];
+
+/// parses a template and checks that it only uses known fields
+fn parse_template(value: &str) -> Result<Template, String> {
+    let template: Template = value.parse()?;
+    if let Some(field) = template
+        .fields()
+        .find(|field| !TEMPLATE_FIELDS.contains(field))
+    {
+        return Err(format!(
+            "Unknown field {{{}}}, the fields are {}",
+            field,
+            TEMPLATE_FIELDS.join(", ")
+        ));
+    }
+    Ok(template)
+}
+
+/// the columns of the csv, tsv and Markdown outputs
+const OUTPUT_COLUMNS: &[&str] = &[
+    "target",
+    "archive",
+    "provider",
+    "timestamp",
+    "token",
+    "status",
+    "error",
+];
+
+/// the output file, written row by row while the links are captured
+struct OutputFile {
+    path: PathBuf,
+    format: OutputFormat,
+    archives_only: bool,
+    /// the lines of the template format
+    template: Option<Template>,
+    /// whether to append to an existing file
+    append: bool,
+    /// opened with the first row
+    file: Option<io::BufWriter<fs::File>>,
+    /// the rows of a json array, which is written at once when finished
+    rows: Vec<Output>,
+    archived: usize,
+    failed: usize,
+}
+
+impl OutputFile {
+    fn new(opts: &Opts, path: &Path) -> Self {
+        OutputFile {
+            path: path.to_path_buf(),
+            format: opts.output_format(),
+            archives_only: opts.archives_only,
+            template: opts.template(),
+            append: opts.append,
+            file: None,
+            rows: Vec::new(),
+            archived: 0,
+            failed: 0,
+        }
+    }
+
+    /// the columns of the header line
+    fn columns(&self) -> &'static [&'static str] {
+        if self.archives_only {
+            &OUTPUT_COLUMNS[1..2]
+        } else {
+            OUTPUT_COLUMNS
+        }
+    }
+
+    /// the values of `output` for the columns
+    fn values(&self, output: &Output) -> Vec<String> {
+        if self.archives_only {
+            return vec![output.archive.clone()];
+        }
+        vec![
+            output.target.clone(),
+            output.archive.clone(),
+            output.provider.to_string(),
+            output
+                .timestamp
+                .map(|timestamp| timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+                .unwrap_or_default(),
+            output.token.clone(),
+            output.status.to_string(),
+            output.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    /// the file, opened and with the header written if it's new
+    fn file(&mut self) -> io::Result<&mut io::BufWriter<fs::File>> {
+        use ::std::io::prelude::*;
+        if self.file.is_none() {
+            let file = if self.append && self.path.exists() {
+                fs::OpenOptions::new().append(true).open(&self.path)?
             } else {
-                let content = if self.archives_only {
-                    let content: Vec<_> = archives.into_iter().map(|x| x.archive).collect();
-                    serde_json::to_string_pretty(&content)
-                } else {
-                    serde_json::to_string_pretty(&archives)
-                }
-                .expect("Failed to convert to json.");
-                if let Err(e) = write!(file, "{}", content) {
-                    if !self.silent {
-                        eprintln!("Couldn't write to file: {}", e);
+                fs::File::create(&self.path)?
+            };
+            let header = file.metadata()?.len() == 0;
+            let mut file = io::BufWriter::new(file);
+            if header {
+                let columns = self.columns();
+                match self.format {
+                    OutputFormat::Csv => writeln!(file, "{}", columns.join(","))?,
+                    OutputFormat::Tsv => writeln!(file, "{}", columns.join("\t"))?,
+                    OutputFormat::Markdown => {
+                        writeln!(file, "| {} |", columns.join(" | "))?;
+                        writeln!(file, "|{}", " --- |".repeat(columns.len()))?;
                     }
+                    _ => {}
                 }
             }
-            if !self.silent {
-                println!("Wrote {} archived links to: {}", len, out.display());
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("is opened"))
+    }
+
+    /// writes the row of a successful or failed capture
+    ///
+    /// Failed captures are only written by the formats with a status column.
+    fn write(&mut self, output: Output) -> io::Result<()> {
+        use ::std::io::prelude::*;
+        if output.status == Status::Failed {
+            if self.archives_only
+                || matches!(self.format, OutputFormat::Text | OutputFormat::Template)
+            {
+                return Ok(());
+            }
+            self.failed += 1;
+        } else {
+            self.archived += 1;
+        }
+        if self.format == OutputFormat::Json {
+            self.rows.push(output);
+            return Ok(());
+        }
+        let values = self.values(&output);
+        let line = self
+            .template
+            .as_ref()
+            .map(|template| output.render(template));
+        let format = self.format;
+        let archives_only = self.archives_only;
+        let file = self.file()?;
+        match format {
+            OutputFormat::Json => {}
+            OutputFormat::Ndjson if archives_only => {
+                writeln!(file, "{}", serde_json::to_string(&output.archive)?)?
+            }
+            OutputFormat::Ndjson => writeln!(file, "{}", serde_json::to_string(&output)?)?,
+            OutputFormat::Csv => {
+                let mut csv = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(&mut *file);
+                csv.write_record(&values)?;
+                csv.flush()?;
+            }
+            OutputFormat::Tsv => {
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| value.replace(['\t', '\n', '\r'], " "))
+                    .collect();
+                writeln!(file, "{}", values.join("\t"))?
+            }
+            OutputFormat::Markdown => {
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| value.replace('|', "\\|").replace(['\n', '\r'], " "))
+                    .collect();
+                writeln!(file, "| {} |", values.join(" | "))?
+            }
+            OutputFormat::Text if archives_only => writeln!(file, "{}", output.archive)?,
+            OutputFormat::Text => writeln!(file, "{}\t{}", output.target, output.archive)?,
+            OutputFormat::Template => writeln!(file, "{}", line.unwrap_or_default())?,
+        }
+        // rows written as they are captured survive an interrupted run
+        file.flush()
+    }
+
+    /// writes the rows of `capture`, with what the input tells about its links
+    fn write_capture(
+        &mut self,
+        capture: &MultiArchived,
+        metadata: &HashMap<String, Metadata>,
+        originals: &HashMap<Url, Vec<Url>>,
+    ) -> io::Result<()> {
+        for archived in capture.successes() {
+            self.write(Output::from(archived.clone()).describe(metadata, originals))?;
+        }
+        for (provider, err) in capture.failures() {
+            self.write(
+                Output::failed(&capture.target_url, provider, err).describe(metadata, originals),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// writes the json array, if any, and returns how many archived and failed links were
+    /// written
+    ///
+    /// When appending, the rows are merged into the array of the existing file.
+    fn finish(mut self) -> io::Result<(usize, usize)> {
+        use ::std::io::prelude::*;
+        if self.format != OutputFormat::Json {
+            self.file()?.flush()?;
+            return Ok((self.archived, self.failed));
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let content = if self.archives_only {
+            let mut archives: Vec<String> = self.existing()?;
+            for row in rows {
+                if !archives.contains(&row.archive) {
+                    archives.push(row.archive);
+                }
             }
+            serde_json::to_string_pretty(&archives)
+        } else {
+            serde_json::to_string_pretty(&merge_rows(self.existing()?, rows))
+        }?;
+        let mut file = fs::File::create(&self.path)?;
+        write!(file, "{}", content)?;
+        Ok((self.archived, self.failed))
+    }
+
+    /// the rows of the json array in the existing file, if appending
+    fn existing<T: DeserializeOwned>(&self) -> io::Result<Vec<T>> {
+        if !self.append || !self.path.exists() {
+            return Ok(Vec::new());
         }
+        let content = fs::read_to_string(&self.path)?;
+        if content.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        serde_json::from_str(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "cannot append to a file that is no JSON array of outputs: {}",
+                    err
+                ),
+            )
+        })
     }
 }
 
+/// merges the new `rows` into the `existing` rows of a json output
+///
+/// A row replaces the existing row of the same target and provider, unless it failed and the
+/// existing one was archived. The order of the existing rows is kept.
+///
+/// Rows are deduplicated by target per provider rather than by target alone, a link captured
+/// with several providers keeps one row for each of them.
+fn merge_rows(mut existing: Vec<Output>, rows: Vec<Output>) -> Vec<Output> {
+    let mut index: HashMap<(String, Provider), usize> = existing
+        .iter()
+        .enumerate()
+        .map(|(idx, row)| ((row.target.clone(), row.provider.clone()), idx))
+        .collect();
+    for row in rows {
+        let key = (row.target.clone(), row.provider.clone());
+        match index.get(&key) {
+            Some(&idx)
+                if row.status == Status::Failed && existing[idx].status == Status::Archived => {}
+            Some(&idx) => existing[idx] = row,
+            None => {
+                index.insert(key, existing.len());
+                existing.push(row);
+            }
+        }
+    }
+    existing
+}
+
 /// type for storing captures to a file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Output {
     /// The requested url to archive with the archive.is capture service
     target: String,
     /// The archive.is url that archives the `target_url`, if archive was successful
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     archive: String,
+    /// The archive service that stores the `archive`, archive.is for outputs written before
+    /// there were other providers
+    #[serde(default)]
+    provider: Provider,
+    /// When the `target` was archived, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<DateTime<Utc>>,
+    /// The submit token the capture was authorized with, if any
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    token: String,
+    /// Whether the capture was successful
+    #[serde(default = "archived")]
+    status: Status,
+    /// Why the capture failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The links of the input that were normalized to `target`, if any of them differs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    originals: Vec<String>,
+    /// What is known about the `target` from the input
+    #[serde(flatten)]
+    metadata: Metadata,
+}
+
+/// the status of outputs without one, written before failures were part of the output
+fn archived() -> Status {
+    Status::Archived
+}
+
+impl Output {
+    /// the output of a failed capture
+    fn failed(target: &Url, provider: &Provider, err: &archiveis::Error) -> Self {
+        Output {
+            target: target.to_string(),
+            archive: String::new(),
+            provider: provider.clone(),
+            timestamp: None,
+            token: String::new(),
+            status: Status::Failed,
+            error: Some(err.to_string()),
+            originals: Vec::new(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// the line of `template` for this output
+    fn render(&self, template: &Template) -> String {
+        template.render(|field| match field {
+            "archive" => Some(self.archive.clone()),
+            "target" => Some(self.target.clone()),
+            "provider" => Some(self.provider.to_string()),
+            "timestamp" => self
+                .timestamp
+                .map(|timestamp| timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            "token" => Some(self.token.clone()),
+            "title" => self.metadata.title.clone(),
+            "folder" => self.metadata.folder.clone(),
+            "tags" => Some(self.metadata.tags.join(",")),
+            _ => None,
+        })
+    }
+
+    /// adds the links of the input that were normalized to the `target` and what the input
+    /// tells about them
+    fn describe(
+        mut self,
+        metadata: &HashMap<String, Metadata>,
+        originals: &HashMap<Url, Vec<Url>>,
+    ) -> Self {
+        let links = self
+            .target
+            .parse()
+            .ok()
+            .and_then(|target: Url| originals.get(&target))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(metadata) = std::iter::once(self.target.as_str())
+            .chain(links.iter().map(Url::as_str))
+            .find_map(|url| metadata.get(url))
+        {
+            self.metadata = metadata.clone();
+        }
+        if links.iter().any(|link| link.as_str() != self.target) {
+            self.originals = links.iter().map(Url::to_string).collect();
+        }
+        self
+    }
 }
 
 impl From<Archived> for Output {
@@ -142,120 +1159,1324 @@ impl From<Archived> for Output {
         Output {
             target: archive.target_url,
             archive: archive.archived_url,
+            provider: archive.provider,
+            timestamp: archive.time_stamp,
+            token: archive.submit_token,
+            status: Status::Archived,
+            error: None,
+            originals: Vec::new(),
+            metadata: Metadata::default(),
         }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    pretty_env_logger::try_init()?;
+/// what the input tells about a link besides its url, like the title of a bookmark
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Metadata {
+    /// The title of the link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// The folder of a bookmark, like `Bookmarks bar/Work`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    folder: Option<String>,
+    /// The tags of a bookmark
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+compile_error!("the archiveis binary requires the `runtime-tokio` or `runtime-async-std` feature");
+
+#[cfg_attr(feature = "runtime-tokio", tokio::main)]
+#[cfg_attr(
+    all(feature = "runtime-async-std", not(feature = "runtime-tokio")),
+    async_std::main
+)]
+async fn main() {
+    let _ = pretty_env_logger::try_init();
     let app = App::from_args();
+    let silent = app.opts().silent;
+
+    let exit = match run(app).await {
+        Ok(exit) => exit,
+        Err(Failure(exit, msg)) => {
+            if !silent {
+                eprintln!("{}", msg);
+            }
+            exit
+        }
+    };
+    ::std::process::exit(exit as i32);
+}
 
-    let client = ArchiveClient::default();
+/// The documented exit codes of the binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// every link was archived or rejected by the filters, or failures are ignored
+    Success = 0,
+    /// some links could not be archived
+    PartialFailure = 1,
+    /// no link could be archived
+    TotalFailure = 2,
+    /// the input could not be read or contains invalid links
+    BadInput = 3,
+    /// no submit token could be obtained from archive.is
+    TokenFailure = 4,
+    /// an archive service rate limited the requests or asked to solve a CAPTCHA
+    RateLimited = 5,
+    /// the output, failures file, journal or database could not be written
+    Output = 6,
+}
 
-    let (links, opts) = match app {
-        App::File { input, opts } => {
-            let reader = BufReader::new(
-                fs::File::open(&input).expect(&format!("Cannot open {}", input.display())),
-            );
-            let links = reader
-                .lines()
-                .map(Result::unwrap)
-                .map(|link| {
-                    link.trim()
-                        .parse::<Url>()
-                        .expect(&format!("Link {} is no valid uri.", link))
-                })
-                .collect::<Vec<_>>();
-            (links, opts)
+impl Exit {
+    /// the exit code for a run where `failures` could not be archived
+    fn from_failures(failures: &[&MultiArchived], archived: usize) -> Self {
+        let errors = || failures.iter().flat_map(|capture| capture.failures());
+        if errors().any(|(_, err)| matches!(err, archiveis::Error::RateLimited(_))) {
+            Exit::RateLimited
+        } else if errors().any(|(_, err)| matches!(err, archiveis::Error::MissingToken)) {
+            Exit::TokenFailure
+        } else if archived == 0 {
+            Exit::TotalFailure
+        } else {
+            Exit::PartialFailure
         }
-        App::Links { links, opts } => (links, opts),
+    }
+}
+
+/// whether the page of a sitemap was modified at or after `since` and after its latest
+/// successful capture in `store`
+///
+/// Pages without a modification date are always considered modified.
+fn modified(
+    page: &SitemapEntry,
+    since: Option<DateTime<Utc>>,
+    store: Option<&SqliteStore>,
+    archiver: &MultiArchiver,
+) -> bool {
+    let lastmod = match page.lastmod {
+        Some(lastmod) => lastmod,
+        None => return true,
     };
+    if since.is_some_and(|since| lastmod < since) {
+        return false;
+    }
+    let captured = store
+        .and_then(|store| store.by_url(archiver.target_url(&page.loc).as_str()).ok())
+        .and_then(|records| {
+            records
+                .into_iter()
+                .filter(|record| record.status == Status::Archived)
+                .map(|record| record.time_stamp)
+                .max()
+        });
+    match captured {
+        Some(captured) => lastmod > captured,
+        None => true,
+    }
+}
 
-    if links.is_empty() {
-        if !opts.silent {
-            eprintln!("Nothing to archive.");
+/// parses a date like `2024-01-31` or `2024-01-31T12:00:00Z`
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|err| format!("Invalid date {}: {}", value, err))
+}
+
+/// the domains of the archive services
+const ARCHIVE_DOMAINS: &[&str] = &[
+    "archive.is",
+    "archive.today",
+    "archive.ph",
+    "archive.li",
+    "archive.vn",
+    "archive.fo",
+    "archive.md",
+    "web.archive.org",
+];
+
+/// reads the whole file at `input`, or stdin if `input` is `-`
+fn read_input(input: &Path) -> Result<String, Failure> {
+    if input == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content).map(|_| content)
+    } else {
+        fs::read_to_string(input)
+    }
+    .map_err(fail(
+        Exit::BadInput,
+        format!("Cannot read {}", input.display()),
+    ))
+}
+
+/// an error that aborts the run with an exit code and a message
+struct Failure(Exit, String);
+
+/// wraps an error into a `Failure` with the exit code and a description of what failed
+fn fail<E: std::fmt::Display>(exit: Exit, context: String) -> impl FnOnce(E) -> Failure {
+    move |err| Failure(exit, format!("{}: {}", context, err))
+}
+
+/// the links of a subcommand, together with what is needed to finish it once they are archived
+struct Source<'a> {
+    links: LocalBoxStream<'a, Result<Url, InputError>>,
+    /// the metadata of the links by their url
+    metadata: HashMap<String, Metadata>,
+    rewrite: Option<Rewrite>,
+    report: Option<ScanReport>,
+    /// the new entries of a feed, remembered once they are archived
+    feed_entries: Option<(String, Vec<FeedEntry>)>,
+    /// the sitemap and the number of its pages, changed or not
+    sitemap: Option<(String, Rc<Cell<usize>>)>,
+}
+
+impl<'a> Source<'a> {
+    /// a source that reads the `links` and nothing else
+    fn new(links: LocalBoxStream<'a, Result<Url, InputError>>) -> Self {
+        Source {
+            links,
+            metadata: HashMap::new(),
+            rewrite: None,
+            report: None,
+            feed_entries: None,
+            sitemap: None,
         }
-        ::std::process::exit(1);
     }
 
-    let token = client.get_unique_token().await?;
-    let archives = stream::iter(
-        links
-            .into_iter()
-            .map(|url| async { client.capture_with_token(url, &token).await }),
-    )
-    .buffer_unordered(10)
-    .collect::<Vec<_>>()
-    .await;
+    /// a source of the already known `urls`
+    fn urls(urls: Vec<Url>) -> Self {
+        Source::new(stream::iter(urls.into_iter().map(Ok)).boxed_local())
+    }
+}
 
-    let archives = retry(&client, archives, opts.retries).await;
+/// reads the links of the subcommand `app`
+async fn source<'a>(
+    app: App,
+    store: Option<&'a SqliteStore>,
+    archiver: &'a MultiArchiver,
+) -> Result<(Source<'a>, Opts), Failure> {
+    Ok(match app {
+        App::File { input, opts } => (Source::new(read_links(&input, opts.auto_scheme)?), opts),
+        App::Links { links, opts } => (Source::urls(links), opts),
+        App::Extract {
+            input,
+            document,
+            opts,
+        } => {
+            let (content, format) = document.read(&input)?;
+            (
+                Source::urls(document.extractor().urls(&content, format)),
+                opts,
+            )
+        }
+        App::Bookmarks {
+            input,
+            format,
+            url_column,
+            title_column,
+            tags_column,
+            folders,
+            opts,
+        } => {
+            let columns = CsvColumns::new()
+                .url(url_column)
+                .title(title_column)
+                .tags(tags_column);
+            let (urls, metadata) = read_bookmarks(&input, format, &columns, &folders)?;
+            let source = Source {
+                metadata,
+                ..Source::urls(urls)
+            };
+            (source, opts)
+        }
+        App::Rewrite {
+            input,
+            mode,
+            rewrite_to,
+            document,
+            opts,
+        } => {
+            let rewrite = Rewrite::read(input, rewrite_to, &document, mode)?;
+            let urls = rewrite.urls();
+            let source = Source {
+                rewrite: Some(rewrite),
+                ..Source::urls(urls)
+            };
+            (source, opts)
+        }
+        App::Scan {
+            dir,
+            hidden,
+            report,
+            opts,
+        } => {
+            let report = ScanReport::scan(&dir, hidden, report)?;
+            let urls = report
+                .scanned
+                .iter()
+                .map(|scanned| scanned.url.clone())
+                .collect();
+            let source = Source {
+                report: Some(report),
+                ..Source::urls(urls)
+            };
+            (source, opts)
+        }
+        App::Sitemap {
+            sitemap,
+            since,
+            changed,
+            opts,
+        } => {
+            let store = store.filter(|_| changed);
+            (read_sitemap(sitemap, since, store, archiver).await?, opts)
+        }
+        App::Feed { feed, opts } => {
+            let entries = new_entries(&feed, store).await?;
+            let mut urls: Vec<Url> = Vec::new();
+            for entry in &entries {
+                if !urls.contains(&entry.link) {
+                    urls.push(entry.link.clone());
+                }
+            }
+            let source = Source {
+                feed_entries: Some((feed, entries)),
+                ..Source::urls(urls)
+            };
+            (source, opts)
+        }
+    })
+}
 
-    if archives.iter().any(Result::is_err) && !opts.ignore_failures {
-        if !opts.silent {
-            let failures: Vec<_> = archives
-                .into_iter()
-                .filter(Result::is_err)
-                .map(Result::unwrap_err)
-                .filter_map(|x| match x {
-                    archiveis::Error::ServerError(url) | archiveis::Error::MissingUrl(url) => {
-                        Some(url)
+/// reads the bookmarks of the export at `input` in the `folders`, or all if there are none
+///
+/// Returns the urls of the bookmarks without duplicates, and their titles, folders and tags.
+fn read_bookmarks(
+    input: &Path,
+    format: Option<BookmarkFormat>,
+    columns: &CsvColumns,
+    folders: &[String],
+) -> Result<(Vec<Url>, HashMap<String, Metadata>), Failure> {
+    let content = read_input(input)?;
+    let format = format
+        .or_else(|| BookmarkFormat::guess(&content))
+        .ok_or_else(|| {
+            Failure(
+                Exit::BadInput,
+                format!(
+                    "Cannot guess the bookmark format of {}, use --input-format",
+                    input.display()
+                ),
+            )
+        })?;
+    let bookmarks = match format {
+        BookmarkFormat::Csv => parse_csv(&content, columns),
+        format => parse_bookmarks(&content, format),
+    }
+    .map_err(fail(
+        Exit::BadInput,
+        format!("Cannot read bookmarks {}", input.display()),
+    ))?;
+    let mut urls = Vec::new();
+    let mut metadata = HashMap::new();
+    for bookmark in bookmarks {
+        if !folders.is_empty() && !folders.iter().any(|folder| bookmark.in_folder(folder)) {
+            continue;
+        }
+        if metadata.contains_key(bookmark.url.as_str()) {
+            continue;
+        }
+        let folder = Some(bookmark.folder_path()).filter(|folder| !folder.is_empty());
+        metadata.insert(
+            bookmark.url.to_string(),
+            Metadata {
+                title: bookmark.title,
+                folder,
+                tags: bookmark.tags,
+            },
+        );
+        urls.push(bookmark.url);
+    }
+    Ok((urls, metadata))
+}
+
+/// reads the pages of the sitemap at the url or path `sitemap`, that were modified according to
+/// `since` and the captures in `store`
+///
+/// The pages are read while the remaining sitemaps of an index are still fetched.
+async fn read_sitemap<'a>(
+    sitemap: String,
+    since: Option<DateTime<Utc>>,
+    store: Option<&'a SqliteStore>,
+    archiver: &'a MultiArchiver,
+) -> Result<Source<'a>, Failure> {
+    let client = SitemapClient::default();
+    let root = match sitemap.parse::<Url>() {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => client.fetch(&url).await,
+        _ => fs::read(&sitemap)
+            .map_err(archiveis::Error::from)
+            .and_then(|content| Sitemap::from_bytes(&content)),
+    }
+    .map_err(fail(
+        Exit::BadInput,
+        format!("Cannot read sitemap {}", sitemap),
+    ))?;
+    let pages_read = Rc::new(Cell::new(0));
+    let read = Rc::clone(&pages_read);
+    let pages = client.pages(root).filter_map(move |page| {
+        if page.is_ok() {
+            read.set(read.get() + 1);
+        }
+        future::ready(match page {
+            Ok(page) if modified(&page, since, store, archiver) => Some(Ok(page.loc)),
+            Ok(_) => None,
+            Err(err) => Some(Err(InputError::Invalid(Invalid {
+                input: "sitemap:".to_string(),
+                reason: err.to_string(),
+            }))),
+        })
+    });
+    Ok(Source {
+        sitemap: Some((sitemap, pages_read)),
+        ..Source::new(pages.boxed_local())
+    })
+}
+
+/// the entries of the feed at the url or path `feed` that are new since they were last seen
+/// according to `store`
+async fn new_entries(feed: &str, store: Option<&SqliteStore>) -> Result<Vec<FeedEntry>, Failure> {
+    let parsed = match feed.parse::<Url>() {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+            FeedClient::default().fetch(&url).await
+        }
+        _ => fs::read_to_string(feed)
+            .map_err(archiveis::Error::from)
+            .and_then(|xml| Feed::parse(&xml, None)),
+    }
+    .map_err(fail(Exit::BadInput, format!("Cannot read feed {}", feed)))?;
+    let mut entries = Vec::new();
+    for entry in parsed.entries {
+        let seen = match store {
+            Some(store) => store
+                .seen(feed, &entry.id)
+                .map_err(fail(Exit::Output, "Cannot read database".to_string()))?,
+            None => None,
+        };
+        if entry.is_new(seen.as_ref()) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+async fn run(app: App) -> Result<Exit, Failure> {
+    let db_error = |db: &Path| {
+        fail(
+            Exit::Output,
+            format!("Cannot write database {}", db.display()),
+        )
+    };
+    // a dry run doesn't create a new database
+    let store = match &app.opts().db {
+        Some(db) if !app.opts().dry_run || db.exists() => {
+            Some(SqliteStore::open(db).map_err(db_error(db))?)
+        }
+        _ => None,
+    };
+    let archiver = app.opts().archiver();
+    let (source, opts) = source(app, store.as_ref(), &archiver).await?;
+    let Source {
+        links,
+        metadata,
+        rewrite,
+        report,
+        feed_entries,
+        sitemap,
+    } = source;
+    if let Some((feed, entries)) = &feed_entries {
+        if entries.is_empty() {
+            if !opts.silent {
+                println!("No new entries in {}.", feed);
+            }
+            return Ok(Exit::Success);
+        }
+    }
+
+    let journal_error = || {
+        let path = opts.journal.as_deref().unwrap_or_else(|| Path::new(""));
+        fail(
+            Exit::Output,
+            format!("Cannot write journal {}", path.display()),
+        )
+    };
+    let journal = opts.journal().map_err(journal_error())?;
+    // every result is recorded as soon as it arrives, so an interrupted run keeps its history
+    let record_capture = |capture: &MultiArchived, skip: &[Provider]| -> Result<(), Failure> {
+        if let Some(journal) = &journal {
+            record(journal, capture, skip).map_err(journal_error())?;
+        }
+        if let (Some(store), Some(db)) = (&store, &opts.db) {
+            record(store, capture, skip).map_err(db_error(db))?;
+        }
+        Ok(())
+    };
+
+    let mut read = 0;
+    let mut invalid = Vec::new();
+    // the archives of links that were already captured by a previous run
+    let mut resumed = Vec::new();
+    let mut skipped = 0;
+    // all links of the input by the url they are captured with
+    let originals: RefCell<HashMap<Url, Vec<Url>>> = RefCell::new(HashMap::new());
+    let mut duplicates = 0;
+    let mut rejected = Vec::new();
+    let mut captures = Vec::new();
+    // the links a dry run would capture, with their existing snapshots if looked up
+    let mut planned = Vec::new();
+    let output_error = || {
+        let path = opts.output.as_deref().unwrap_or_else(|| Path::new(""));
+        fail(Exit::Output, format!("Cannot write {}", path.display()))
+    };
+    let mut output = match &opts.output {
+        Some(path) if !opts.dry_run => Some(OutputFile::new(&opts, path)),
+        _ => None,
+    };
+    // the captures whose rows were already written to the output
+    let mut written = HashSet::new();
+    {
+        let journal = &journal;
+        let archiver = &archiver;
+        let links = links.filter_map(|link| {
+            let link = match link {
+                Ok(link) => {
+                    read += 1;
+                    if let Err(rejection) = archiver.check(&link) {
+                        rejected.push((link, rejection));
+                        return future::ready(None);
+                    }
+                    let target = archiver.target_url(&link);
+                    let mut originals = originals.borrow_mut();
+                    let links = originals.entry(target.clone()).or_default();
+                    links.push(link);
+                    if links.len() > 1 {
+                        duplicates += 1;
+                        return future::ready(None);
+                    }
+                    Ok(target)
+                }
+                Err(InputError::Invalid(entry)) => {
+                    invalid.push(entry);
+                    return future::ready(None);
+                }
+                Err(InputError::Fatal(err)) => Err(err),
+            };
+            let link = match (link, journal) {
+                (Ok(link), Some(journal)) if opts.resume => {
+                    match previous_archives(archiver, journal, &link) {
+                        Ok(archives) if archives.is_empty() => Some(Ok(link)),
+                        Ok(archives) => {
+                            skipped += 1;
+                            resumed.extend(archives);
+                            None
+                        }
+                        Err(err) => Some(Err(journal_error()(err))),
+                    }
+                }
+                (link, _) => Some(link),
+            };
+            future::ready(link)
+        });
+        if opts.dry_run {
+            let lookup = opts.lookup;
+            let mut pending = links
+                .map(|link| async move {
+                    match link {
+                        Ok(link) if lookup => {
+                            let existing = archiver.lookup(&link).await;
+                            Ok((link, existing))
+                        }
+                        Ok(link) => Ok((link, Vec::new())),
+                        Err(err) => Err(err),
                     }
-                    _ => None,
                 })
-                .collect();
-            eprintln!("Failed to archive links: {:?}", failures);
-        }
-    } else {
-        let successes: Vec<Output> = archives
-            .into_iter()
-            .filter_map(|x| {
-                if let Ok(archive) = x {
-                    Some(archive.into())
-                } else {
-                    None
+                .buffered(10);
+            while let Some(link) = pending.next().await {
+                planned.push(link?);
+            }
+        } else {
+            let mut pending = links
+                .map(|link| async move {
+                    match link {
+                        Ok(link) => Ok(archiver.capture(&link).await),
+                        Err(err) => Err(err),
+                    }
+                })
+                .buffer_unordered(10);
+            while let Some(capture) = pending.next().await {
+                let capture = capture?;
+                record_capture(&capture, &[])?;
+                // incomplete captures are written once they were retried
+                if let Some(output) = output
+                    .as_mut()
+                    .filter(|_| capture.is_complete(archiver.policy()))
+                {
+                    output
+                        .write_capture(&capture, &metadata, &originals.borrow())
+                        .map_err(output_error())?;
+                    written.insert(capture.target_url.clone());
                 }
-            })
-            .collect();
+                captures.push(capture);
+            }
+        }
+    }
+    let originals = originals.into_inner();
+    for capture in &mut captures {
+        if let Some(links) = originals.get(&capture.target_url) {
+            capture.originals = links.clone();
+        }
+    }
 
+    if !opts.silent && !invalid.is_empty() {
+        eprintln!("Skipped {} invalid entries of the input:", invalid.len());
+        for invalid in &invalid {
+            eprintln!("  {} {}", invalid.input, invalid.reason);
+        }
+    }
+    if let Some((sitemap, _)) = sitemap.filter(|(_, pages)| read == 0 && pages.get() > 0) {
+        // a release that changed nothing
+        if !opts.silent {
+            println!("No changed pages in {}.", sitemap);
+        }
+        return Ok(if invalid.is_empty() || opts.ignore_failures {
+            Exit::Success
+        } else {
+            Exit::BadInput
+        });
+    }
+    if read == 0 {
+        let msg = if invalid.is_empty() {
+            "Nothing to archive."
+        } else {
+            "The input contains no valid links."
+        };
+        return Err(Failure(Exit::BadInput, msg.to_string()));
+    }
+    if !opts.silent && !rejected.is_empty() {
+        println!("Rejected {} links by the filters:", rejected.len());
+        for (link, rejection) in &rejected {
+            println!("  {} {}", link, rejection);
+        }
+    }
+    if !opts.silent && skipped > 0 {
+        println!(
+            "Skipped {} links already archived according to the journal.",
+            skipped
+        );
+    }
+    if !opts.silent && duplicates > 0 {
+        println!(
+            "Skipped {} duplicate links that are the same as another link after normalizing.",
+            duplicates
+        );
+    }
+    if opts.dry_run {
+        if !opts.silent {
+            print_plan(&archiver, &planned, &originals);
+        }
+        return Ok(if invalid.is_empty() || opts.ignore_failures {
+            Exit::Success
+        } else {
+            Exit::BadInput
+        });
+    }
+    let captures = retry(&archiver, captures, opts.retries, &record_capture).await?;
+
+    let successes: Vec<Archived> = resumed
+        .iter()
+        .cloned()
+        .chain(captures.iter().flat_map(MultiArchived::successes).cloned())
+        .collect();
+    if let (Some(store), Some(db), Some((feed, entries))) = (&store, &opts.db, &feed_entries) {
+        // entries that couldn't be archived are tried again by the next run
+        for entry in entries.iter().filter(|entry| {
+            successes
+                .iter()
+                .any(|archived| archived.target_url == archiver.target_url(&entry.link).as_str())
+        }) {
+            store
+                .mark_seen(&SeenEntry {
+                    feed_url: feed.clone(),
+                    entry_id: entry.id.clone(),
+                    updated: entry.updated,
+                    time_stamp: Utc::now(),
+                })
+                .map_err(db_error(db))?;
+        }
+    }
+    if let Some(rewrite) = &rewrite {
+        rewrite.write(&archiver, &successes).map_err(fail(
+            Exit::Output,
+            format!("Cannot write {}", rewrite.target.display()),
+        ))?;
+        if !opts.silent {
+            println!("Rewrote {}", rewrite.target.display());
+        }
+    }
+    // a report without file replaces the list of archives on stdout
+    let print_report = !opts.silent && report.as_ref().is_some_and(|r| r.target.is_none());
+    if let Some(report) = &report {
+        if let Some(target) = &report.target {
+            report
+                .write(&archiver, &successes, opts.text)
+                .map_err(fail(
+                    Exit::Output,
+                    format!("Cannot write {}", target.display()),
+                ))?;
+            if !opts.silent {
+                println!(
+                    "Wrote the report of {} links to: {}",
+                    report.scanned.len(),
+                    target.display()
+                );
+            }
+        } else if print_report {
+            report
+                .write(&archiver, &successes, opts.text)
+                .map_err(fail(Exit::Output, "Cannot print the report".to_string()))?;
+        }
+    }
+    let archived = successes.len();
+    // the lines of a template without output file replace the list of archives on stdout, also
+    // when silent
+    let template = opts.template().filter(|_| opts.output.is_none());
+    if let Some(template) = &template {
+        for success in &successes {
+            let output = Output::from(success.clone()).describe(&metadata, &originals);
+            println!("{}", output.render(template));
+        }
+    } else if !opts.silent && !print_report {
+        for success in &successes {
+            println!(
+                "Archived {}  -->  {}  ({})",
+                success.target_url, success.archived_url, success.provider
+            );
+        }
+    }
+    if let Some(mut output) = output {
+        for archived in &resumed {
+            output
+                .write(Output::from(archived.clone()).describe(&metadata, &originals))
+                .map_err(output_error())?;
+        }
+        for capture in captures
+            .iter()
+            .filter(|capture| !written.contains(&capture.target_url))
+        {
+            output
+                .write_capture(capture, &metadata, &originals)
+                .map_err(output_error())?;
+        }
+        let path = output.path.clone();
+        let (archived, failed) = output.finish().map_err(output_error())?;
         if !opts.silent {
-            for success in &successes {
-                println!("Archived {}  -->  {}", success.target, success.archive);
+            if failed > 0 {
+                println!(
+                    "Wrote {} archived and {} failed links to: {}",
+                    archived,
+                    failed,
+                    path.display()
+                );
+            } else {
+                println!("Wrote {} archived links to: {}", archived, path.display());
             }
         }
+    }
 
-        opts.write_output(successes);
+    let failures: Vec<_> = captures
+        .iter()
+        .filter(|capture| !capture.is_complete(archiver.policy()))
+        .collect();
+    if failures.is_empty() {
+        return Ok(if invalid.is_empty() || opts.ignore_failures {
+            Exit::Success
+        } else {
+            Exit::BadInput
+        });
+    }
+    if !opts.silent {
+        for capture in &failures {
+            for (provider, err) in capture.failures() {
+                eprintln!(
+                    "Failed to archive {} with {}: {}",
+                    capture.target_url, provider, err
+                );
+            }
+        }
     }
+    if let Some(out) = &opts.failures {
+        opts.write_failures(&failures).map_err(fail(
+            Exit::Output,
+            format!("Cannot write {}", out.display()),
+        ))?;
+    }
+    if opts.ignore_failures {
+        Ok(Exit::Success)
+    } else {
+        Ok(Exit::from_failures(&failures, archived))
+    }
+}
 
-    Ok(())
+/// the latest existing snapshots of a link by provider
+type Existing = Vec<(Provider, archiveis::Result<Option<Archived>>)>;
+
+/// prints the links a dry run would capture, together with the links of the input they were
+/// normalized from and their existing snapshots
+fn print_plan(
+    archiver: &MultiArchiver,
+    planned: &[(Url, Existing)],
+    originals: &HashMap<Url, Vec<Url>>,
+) {
+    let providers: Vec<_> = archiver
+        .providers()
+        .iter()
+        .map(Provider::to_string)
+        .collect();
+    println!(
+        "Would capture {} links with {}:",
+        planned.len(),
+        providers.join(", ")
+    );
+    for (link, existing) in planned {
+        println!("  {}", link);
+        for original in originals.get(link).into_iter().flatten() {
+            if original != link {
+                println!("    from {}", original);
+            }
+        }
+        for (provider, result) in existing {
+            match result {
+                Ok(Some(archived)) => match archived.time_stamp {
+                    Some(time_stamp) => println!(
+                        "    archived by {} at {}: {}",
+                        provider, time_stamp, archived.archived_url
+                    ),
+                    None => println!("    archived by {}: {}", provider, archived.archived_url),
+                },
+                Ok(None) => println!("    not archived by {} yet", provider),
+                Err(err) => println!("    cannot look up with {}: {}", provider, err),
+            }
+        }
+    }
+    println!("Dry run, nothing was submitted.");
+}
+
+/// a part of the input that is skipped, like a line that is no valid link
+#[derive(Debug, PartialEq)]
+struct Invalid {
+    /// where in the input, like the line number and its content
+    input: String,
+    /// why it is skipped
+    reason: String,
+}
+
+/// why no link could be read from the input
+enum InputError {
+    /// the entry is skipped, reading continues with the next one
+    Invalid(Invalid),
+    /// the input can't be read any further
+    Fatal(Failure),
+}
+
+/// parses a line of the input, `None` if it is blank or a `#` comment
+///
+/// Only the first tab separated column is considered, so that a failures file can be used as
+/// input. With `auto_scheme` links without a scheme, like `example.com`, are prefixed with
+/// `https://`.
+fn parse_line(line: &str, auto_scheme: bool) -> Option<Result<Url, String>> {
+    let link = line.split('\t').next().unwrap_or_default().trim();
+    if link.is_empty() || link.starts_with('#') {
+        return None;
+    }
+    let url = if auto_scheme && !link.contains("://") {
+        format!("https://{}", link).parse::<Url>()
+    } else {
+        link.parse::<Url>()
+    };
+    Some(match url {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(url),
+        Ok(url) => Err(format!("has unsupported scheme {}", url.scheme())),
+        Err(err) => Err(format!("is no valid url: {}", err)),
+    })
+}
+
+/// reads the links of the line separated file at `input`, or of stdin if `input` is `-`
+///
+/// The lines are read in the background and handed out as soon as they arrive, so that links
+/// piped in by another program are archived while that program still runs.
+fn read_links(
+    input: &Path,
+    auto_scheme: bool,
+) -> Result<LocalBoxStream<'static, Result<Url, InputError>>, Failure> {
+    let reader: Box<dyn BufRead + Send> = if input == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let file = fs::File::open(input).map_err(fail(
+            Exit::BadInput,
+            format!("Cannot open {}", input.display()),
+        ))?;
+        Box::new(BufReader::new(file))
+    };
+    let name = input.display().to_string();
+    let (mut sender, receiver) = mpsc::channel(100);
+    thread::spawn(move || {
+        for (idx, line) in reader.lines().enumerate() {
+            let link = match line {
+                Ok(line) => match parse_line(&line, auto_scheme) {
+                    Some(Ok(url)) => Ok(url),
+                    Some(Err(reason)) => Err(InputError::Invalid(Invalid {
+                        input: format!("line {}: {:?}", idx + 1, line),
+                        reason,
+                    })),
+                    None => continue,
+                },
+                Err(err) => Err(InputError::Fatal(fail(
+                    Exit::BadInput,
+                    format!("Cannot read {}", name),
+                )(err))),
+            };
+            let stop = matches!(link, Err(InputError::Fatal(_)));
+            // stop reading if the receiver is gone
+            if executor::block_on(sender.send(link)).is_err() || stop {
+                break;
+            }
+        }
+    });
+    Ok(receiver.boxed_local())
 }
 
 /// retries capturing until are `retries` are exhausted or every link was archived successfully.
-async fn retry(
-    client: &ArchiveClient,
-    archives: Vec<archiveis::Result<Archived>>,
+///
+/// Every retried capture is passed to `record` together with the providers that already
+/// succeeded before.
+async fn retry<F>(
+    archiver: &MultiArchiver,
+    mut captures: Vec<MultiArchived>,
     mut retries: usize,
-) -> Vec<archiveis::Result<Archived>> {
-    let (mut archived, mut failures): (Vec<_>, Vec<_>) =
-        archives.into_iter().partition(Result::is_ok);
-    while retries > 0 || !failures.is_empty() {
-        for idx in (0..failures.len()).rev() {
-            let failure = failures.swap_remove(idx).unwrap_err();
-            let url = match failure {
-                archiveis::Error::ServerError(url) | archiveis::Error::MissingUrl(url) => Some(url),
-                _ => continue,
-            };
+    record: F,
+) -> Result<Vec<MultiArchived>, Failure>
+where
+    F: Fn(&MultiArchived, &[Provider]) -> Result<(), Failure>,
+{
+    while retries > 0
+        && captures
+            .iter()
+            .any(|capture| !capture.is_complete(archiver.policy()))
+    {
+        let mut pending = stream::iter(captures.into_iter().map(|capture| async move {
+            // providers that already succeeded are not captured again
+            let archived: Vec<_> = capture
+                .successes()
+                .map(|archived| archived.provider.clone())
+                .collect();
+            (archived, archiver.retry(capture).await)
+        }))
+        .buffer_unordered(10);
+        captures = Vec::new();
+        while let Some((archived, capture)) = pending.next().await {
+            record(&capture, &archived)?;
+            captures.push(capture);
+        }
+        retries -= 1;
+    }
+    Ok(captures)
+}
 
-            if let Some(url) = url {
-                if let Ok(archive) = client.capture(&url).await {
-                    archived.push(Ok(archive))
-                } else {
-                    failures.push(Err(archiveis::Error::MissingUrl(url)))
+/// writes the results of all providers of `capture` except `skip` to the journal or database
+fn record<S: CaptureStore>(
+    store: &S,
+    capture: &MultiArchived,
+    skip: &[Provider],
+) -> archiveis::Result<()> {
+    for (provider, result) in &capture.results {
+        if !skip.contains(provider) {
+            store.insert(&Record::from_result(
+                &capture.target_url,
+                provider.clone(),
+                result,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// the archives of `link` a previous run recorded in the journal, if it was completely archived
+/// back then
+fn previous_archives(
+    archiver: &MultiArchiver,
+    journal: &Journal,
+    link: &Url,
+) -> archiveis::Result<Vec<Archived>> {
+    let records = journal.by_url(link.as_str())?;
+    let mut archives = Vec::new();
+    for provider in archiver.providers() {
+        // the most recent successful capture with this provider
+        if let Some(archived) = records
+            .iter()
+            .rev()
+            .filter(|record| record.provider == provider && record.status == Status::Archived)
+            .find_map(Record::to_archived)
+        {
+            archives.push(archived);
+        } else if archiver.policy() == Policy::All {
+            return Ok(Vec::new());
+        }
+    }
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerant_lines() {
+        assert_eq!(parse_line("  ", false), None);
+        assert_eq!(parse_line("# a comment", false), None);
+        assert_eq!(
+            parse_line(" http://example.com/ ", false),
+            Some(Ok("http://example.com/".parse().unwrap()))
+        );
+        assert_eq!(
+            parse_line("http://example.com/\tarchiveis\thttp\tfailed", false),
+            Some(Ok("http://example.com/".parse().unwrap()))
+        );
+        assert!(matches!(
+            parse_line("example.com/page", false),
+            Some(Err(_))
+        ));
+        assert_eq!(
+            parse_line("example.com/page", true),
+            Some(Ok("https://example.com/page".parse().unwrap()))
+        );
+        assert!(matches!(
+            parse_line("ftp://example.com/", true),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn format_flags() {
+        let app = App::from_iter_safe(&[
+            "archiveis",
+            "extract",
+            "-i",
+            "README.md",
+            "--input-format",
+            "markdown",
+            "--format",
+            "csv",
+        ])
+        .unwrap();
+        match app {
+            App::Extract { document, opts, .. } => {
+                assert_eq!(document.format, Some(DocumentFormat::Markdown));
+                assert_eq!(opts.output_format(), OutputFormat::Csv);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let app = App::from_iter_safe(&[
+            "archiveis",
+            "bookmarks",
+            "-i",
+            "bookmarks.html",
+            "--input-format",
+            "pocket",
+            "--format",
+            "ndjson",
+        ])
+        .unwrap();
+        match app {
+            App::Bookmarks { format, opts, .. } => {
+                assert_eq!(format, Some(BookmarkFormat::Pocket));
+                assert_eq!(opts.output_format(), OutputFormat::Ndjson);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn output_formats() {
+        let dir = std::env::temp_dir().join(format!("archiveis-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target: Url = "https://example.com/a,b".parse().unwrap();
+        let archived = Archived {
+            target_url: target.to_string(),
+            archived_url: "http://archive.is/x".to_string(),
+            time_stamp: "2024-01-01T00:00:00Z".parse().ok(),
+            submit_token: String::new(),
+            provider: Provider::ArchiveIs,
+        };
+        let failed = Output::failed(
+            &target,
+            &Provider::Wayback,
+            &archiveis::Error::ServerError("a | b".to_string()),
+        );
+        let write = |name: &str, format: &str| {
+            let path = dir.join(name);
+            let opts = Opts::from_iter(&["archiveis", "--format", format]);
+            let mut output = OutputFile::new(&opts, &path);
+            output.write(Output::from(archived.clone())).unwrap();
+            output.write(failed.clone()).unwrap();
+            assert_eq!(output.finish().unwrap(), (1, 1));
+            fs::read_to_string(path).unwrap()
+        };
+
+        assert_eq!(
+            write("out.csv", "csv"),
+            "target,archive,provider,timestamp,token,status,error\n\
+             \"https://example.com/a,b\",http://archive.is/x,archiveis,2024-01-01T00:00:00Z,,archived,\n\
+             \"https://example.com/a,b\",,wayback,,,failed,Encountered server error for a | b\n"
+        );
+        let markdown = write("out.md", "markdown");
+        assert!(markdown.starts_with("| target | archive |"));
+        assert!(markdown.ends_with("| failed | Encountered server error for a \\| b |\n"));
+        let ndjson = write("out.ndjson", "ndjson");
+        let rows: Vec<Output> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows[0].status, Status::Archived);
+        assert_eq!(rows[1].status, Status::Failed);
+        assert_eq!(
+            OutputFormat::guess(Path::new("out.jsonl")),
+            Some(OutputFormat::Ndjson)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repeated_appends() {
+        let dir = std::env::temp_dir().join(format!("archiveis-append-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archived = |target: &str, archive: &str| {
+            Output::from(Archived {
+                target_url: target.to_string(),
+                archived_url: archive.to_string(),
+                time_stamp: None,
+                submit_token: String::new(),
+                provider: Provider::ArchiveIs,
+            })
+        };
+        let failed = |target: &str| {
+            Output::failed(
+                &target.parse().unwrap(),
+                &Provider::ArchiveIs,
+                &archiveis::Error::MissingToken,
+            )
+        };
+        let append = |name: &str, args: &[&str], rows: Vec<Output>| {
+            let path = dir.join(name);
+            let opts = Opts::from_iter(["archiveis", "--append"].iter().chain(args));
+            let mut output = OutputFile::new(&opts, &path);
+            for row in rows {
+                output.write(row).unwrap();
+            }
+            output.finish().unwrap();
+            fs::read_to_string(path).unwrap()
+        };
+
+        let a = "https://example.com/a";
+        let b = "https://example.com/b";
+        append(
+            "out.json",
+            &[],
+            vec![archived(a, "http://archive.is/1"), failed(b)],
+        );
+        append(
+            "out.json",
+            &[],
+            vec![failed(a), archived(b, "http://archive.is/2")],
+        );
+        let json = append("out.json", &[], vec![archived(a, "http://archive.is/3")]);
+        let rows: Vec<Output> = serde_json::from_str(&json).unwrap();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| (row.target.as_str(), row.archive.as_str(), row.status))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (a, "http://archive.is/3", Status::Archived),
+                (b, "http://archive.is/2", Status::Archived)
+            ]
+        );
+
+        append(
+            "archives.json",
+            &["--archives-only"],
+            vec![archived(a, "http://archive.is/1")],
+        );
+        let json = append(
+            "archives.json",
+            &["--archives-only"],
+            vec![
+                archived(a, "http://archive.is/1"),
+                archived(b, "http://archive.is/2"),
+            ],
+        );
+        let archives: Vec<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(archives, vec!["http://archive.is/1", "http://archive.is/2"]);
+
+        let csv = &["--format", "csv"];
+        append("out.csv", csv, vec![archived(a, "http://archive.is/1")]);
+        let csv = append("out.csv", csv, vec![archived(b, "http://archive.is/2")]);
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.matches("target,archive").count(), 1);
+
+        // written by versions that only knew archive.is
+        fs::write(
+            dir.join("old.json"),
+            r#"[{"target": "https://example.com/a", "archive": "http://archive.is/0"}]"#,
+        )
+        .unwrap();
+        let json = append("old.json", &[], vec![archived(b, "http://archive.is/2")]);
+        let rows: Vec<Output> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].provider, Provider::ArchiveIs);
+        assert_eq!(rows[0].status, Status::Archived);
+        let json = append("old.json", &[], vec![archived(a, "http://archive.is/1")]);
+        let rows: Vec<Output> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].archive, "http://archive.is/1");
+
+        fs::write(dir.join("broken.json"), "[{").unwrap();
+        let opts = Opts::from_iter(&["archiveis", "--append"]);
+        let mut output = OutputFile::new(&opts, &dir.join("broken.json"));
+        output.write(archived(a, "http://archive.is/1")).unwrap();
+        assert!(output.finish().is_err());
+        assert_eq!(fs::read_to_string(dir.join("broken.json")).unwrap(), "[{");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// serves `/ok` as a html page and everything else as not found
+    fn serve() -> String {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
                 }
+                let (status, body) = if request.starts_with(b"GET /ok ") {
+                    ("200 OK", "<html><body>ok</body></html>")
+                } else {
+                    ("404 Not Found", "not found")
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
             }
-            retries -= 1;
+        });
+        format!("http://{}", addr)
+    }
+
+    /// the exit code of the binary run with `args`
+    async fn exit_code<S: AsRef<str>>(args: &[S]) -> Exit {
+        let args = std::iter::once("archiveis")
+            .chain(args.iter().map(AsRef::as_ref))
+            .chain(std::iter::once("--silent"));
+        match run(App::from_iter_safe(args).unwrap()).await {
+            Ok(exit) => exit,
+            Err(Failure(exit, _)) => exit,
         }
     }
-    archived.extend(failures.into_iter());
-    archived
+
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    #[cfg_attr(
+        all(feature = "runtime-async-std", not(feature = "runtime-tokio")),
+        async_std::test
+    )]
+    async fn exit_codes() {
+        let dir = std::env::temp_dir().join(format!("archiveis-exit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        // nothing to archive
+        fs::write(dir.join("empty.txt"), "# no links\n\n").unwrap();
+        assert_eq!(
+            exit_code(&["file", "-i", &path("empty.txt")]).await,
+            Exit::BadInput
+        );
+        fs::write(dir.join("invalid.txt"), "not a link\n").unwrap();
+        assert_eq!(
+            exit_code(&["file", "-i", &path("invalid.txt")]).await,
+            Exit::BadInput
+        );
+
+        // rejected links are no failures
+        assert_eq!(
+            exit_code(&[
+                "links",
+                "-i",
+                "http://192.168.1.1/",
+                "http://localhost/",
+                "--reject-private"
+            ])
+            .await,
+            Exit::Success
+        );
+
+        // a release that changed no page
+        fs::write(
+            dir.join("sitemap.xml"),
+            "<urlset><url><loc>https://example.com/</loc><lastmod>2020-01-01</lastmod></url></urlset>",
+        )
+        .unwrap();
+        assert_eq!(
+            exit_code(&["sitemap", &path("sitemap.xml"), "--since", "2030-01-01"]).await,
+            Exit::Success
+        );
+
+        let server = serve();
+        let ok = format!("{}/ok", server);
+        let missing = format!("{}/missing", server);
+        // the arguments to capture `links` as local snapshots
+        let capture = |links: &[&str], args: &[&str]| -> Vec<String> {
+            let snapshots = path("snapshots");
+            ["links", "-i"]
+                .iter()
+                .chain(links)
+                .chain(&["-p", "local", "--snapshot-dir", snapshots.as_str()])
+                .chain(args)
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+        assert_eq!(exit_code(&capture(&[&ok], &[])).await, Exit::Success);
+        assert_eq!(
+            exit_code(&capture(&[&ok, &missing], &[])).await,
+            Exit::PartialFailure
+        );
+        assert_eq!(
+            exit_code(&capture(&[&missing], &[])).await,
+            Exit::TotalFailure
+        );
+        assert_eq!(
+            exit_code(&capture(&[&ok, &missing], &["--ignore-failures"])).await,
+            Exit::Success
+        );
+    }
 }
@@ -0,0 +1,118 @@
+//! A common interface for the supported archive services.
+
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Archived, Result};
+use async_trait::async_trait;
+use std::{convert::Infallible, fmt, str::FromStr};
+use url::Url;
+
+/// The archive service that created a snapshot
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "with-serde",
+    derive(Serialize, Deserialize),
+    serde(from = "String", into = "String")
+)]
+pub enum Provider {
+    /// archive.is, also known as archive.today
+    #[default]
+    ArchiveIs,
+    /// The Wayback Machine of the Internet Archive
+    Wayback,
+    /// Snapshots stored on the local file system
+    Local,
+    /// Any other archive service, identified by its name
+    Other(String),
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Provider::ArchiveIs => write!(f, "archiveis"),
+            Provider::Wayback => write!(f, "wayback"),
+            Provider::Local => write!(f, "local"),
+            Provider::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "archiveis" | "archive.is" | "archivetoday" | "archive.today" => Provider::ArchiveIs,
+            "wayback" | "archive.org" | "web.archive.org" => Provider::Wayback,
+            "local" => Provider::Local,
+            _ => Provider::Other(s.trim().to_string()),
+        })
+    }
+}
+
+impl From<String> for Provider {
+    fn from(name: String) -> Self {
+        match name.parse() {
+            Ok(provider) => provider,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl From<Provider> for String {
+    fn from(provider: Provider) -> Self {
+        provider.to_string()
+    }
+}
+
+/// A service that captures snapshots of websites.
+///
+/// Implemented by [`crate::ArchiveClient`] for archive.is and [`crate::WaybackClient`] for the
+/// Wayback Machine, so that code can target either service, or a custom one.
+#[async_trait]
+pub trait Archiver {
+    /// The service the snapshots are stored at
+    fn provider(&self) -> Provider;
+
+    /// Captures a new snapshot of `url`.
+    async fn capture(&self, url: &Url) -> Result<Archived>;
+
+    /// Looks up the most recent existing snapshot of `url`.
+    ///
+    /// Returns `None` if the service has no snapshot of `url` yet.
+    async fn lookup(&self, url: &Url) -> Result<Option<Archived>>;
+}
+
+#[async_trait]
+impl<A: Archiver + Send + Sync + ?Sized> Archiver for Box<A> {
+    fn provider(&self) -> Provider {
+        (**self).provider()
+    }
+
+    async fn capture(&self, url: &Url) -> Result<Archived> {
+        (**self).capture(url).await
+    }
+
+    async fn lookup(&self, url: &Url) -> Result<Option<Archived>> {
+        (**self).lookup(url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_names() {
+        for provider in &[
+            Provider::ArchiveIs,
+            Provider::Wayback,
+            Provider::Local,
+            Provider::Other("perma.cc".to_string()),
+        ] {
+            assert_eq!(&provider.to_string().parse::<Provider>().unwrap(), provider);
+        }
+        assert_eq!("archive.today".parse::<Provider>(), Ok(Provider::ArchiveIs));
+    }
+}
@@ -0,0 +1,190 @@
+//! A blocking client for the archive.is capture service.
+//!
+//! The blocking `ArchiveClient` mirrors the async [`crate::ArchiveClient`] and returns the same
+//! [`Archived`] and [`Error`](crate::Error) types, but doesn't require an async runtime.
+//! It is only available with the `blocking` feature enabled.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = archiveis::blocking::ArchiveClient::default();
+//! let archived = client.capture("http://example.com/")?;
+//! println!("url of archived site: {}", archived.archived_url);
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "filter")]
+use crate::filter::LinkFilter;
+use crate::{
+    archived_from_response, archived_from_timemap, normalize::Normalizer, submit_body, timemap_url,
+    token_from_response, transport, Archived, IntoUrl, Provider, Result, Targets, ARCHIVE_URL,
+    SUBMIT_URL,
+};
+
+/// A blocking Client that serves as a wrapper around the archive.is capture service
+pub struct ArchiveClient {
+    /// The internal blocking reqwest Client.
+    client: transport::blocking::HttpClient,
+    /// How the requested urls are normalized and filtered.
+    targets: Targets,
+}
+
+impl ArchiveClient {
+    /// Creates a new instance of the blocking `ArchiveClient` using a special user agent
+    pub fn new<T: ToString>(user_agent: T) -> Self {
+        ArchiveClient {
+            client: transport::blocking::HttpClient::new(&user_agent.to_string()),
+            targets: Targets::default(),
+        }
+    }
+
+    /// Normalizes every url with `normalizer` before it is captured or looked up.
+    ///
+    /// See [`crate::ArchiveClient::normalize`].
+    pub fn normalize(mut self, normalizer: Normalizer) -> Self {
+        self.targets.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Captures only the urls accepted by `filter`, rejected urls fail with `Error::Rejected`.
+    ///
+    /// See [`crate::ArchiveClient::filter`].
+    #[cfg(feature = "filter")]
+    pub fn filter(mut self, filter: LinkFilter) -> Self {
+        self.targets.filter = Some(filter);
+        self
+    }
+
+    /// Invokes the archive.is capture service on each url provided.
+    ///
+    /// A fresh token is obtained via `get_unique_token` first, afterwards every url is captured
+    /// one after another. The returned `Vec` holds the result of every individual capturing
+    /// request, so every single capture request gets executed regardless of the success of prior
+    /// requests. With a normalizer, urls that are the same after normalizing are captured once.
+    ///
+    /// Unlike the async [`crate::ArchiveClient::capture_all`], which runs up to 10 requests
+    /// concurrently, the captures are sequential: the call blocks until every url is done, so
+    /// large batches take as long as all captures together. Spawn threads with separate clients
+    /// if that is too slow.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), archiveis::Error> {
+    /// let client = archiveis::blocking::ArchiveClient::default();
+    /// for archived in client.capture_all(vec!["http://example.com/", "https://www.rust-lang.org"])? {
+    ///     match archived {
+    ///         Ok(archived) => println!("{} -> {}", archived.target_url, archived.archived_url),
+    ///         Err(err) => eprintln!("{}", err),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn capture_all<U: IntoUrl>(&self, links: Vec<U>) -> Result<Vec<Result<Archived>>> {
+        let token = self.get_unique_token()?;
+        Ok(self
+            .targets
+            .dedup(links)
+            .into_iter()
+            .map(|url| self.capture_with_token(url?, &token))
+            .collect())
+    }
+
+    /// Invokes the archive.is capture service.
+    ///
+    /// See [`crate::ArchiveClient::capture`].
+    pub fn capture<U: IntoUrl>(&self, url: U) -> Result<Archived> {
+        self.capture_with_token(url, self.get_unique_token()?)
+    }
+
+    /// Invokes the archive.is capture service directly without retrieving a submit id first.
+    ///
+    /// See [`crate::ArchiveClient::capture_with_token`].
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), archiveis::Error> {
+    /// let client = archiveis::blocking::ArchiveClient::default();
+    /// let token = client.get_unique_token()?;
+    /// let archived = client.capture_with_token("http://example.com/", &token)?;
+    /// assert_eq!(archived.submit_token, token);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn capture_with_token<U: IntoUrl, T: ToString>(
+        &self,
+        url: U,
+        submit_token: T,
+    ) -> Result<Archived> {
+        let target_url = self.targets.check(url.into_url()?)?;
+        let submit_token = submit_token.to_string();
+
+        let resp = self
+            .client
+            .post_form(SUBMIT_URL, submit_body(&target_url, &submit_token))?;
+
+        archived_from_response(target_url, submit_token, &resp)
+    }
+
+    /// Obtains a temporarily valid unique token from the archive.is front page.
+    ///
+    /// See [`crate::ArchiveClient::get_unique_token`].
+    pub fn get_unique_token(&self) -> Result<String> {
+        let resp = self.client.get(ARCHIVE_URL)?;
+
+        token_from_response(&resp)
+    }
+
+    /// Looks up the most recent snapshot of `url` on archive.is.
+    ///
+    /// See [`crate::ArchiveClient::lookup`].
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), archiveis::Error> {
+    /// let client = archiveis::blocking::ArchiveClient::default();
+    /// match client.lookup("http://example.com/")? {
+    ///     Some(archived) => println!("latest snapshot: {}", archived.archived_url),
+    ///     None => println!("not archived yet"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lookup<U: IntoUrl>(&self, url: U) -> Result<Option<Archived>> {
+        let target_url = self.targets.normalize(url.into_url()?);
+        let resp = self.client.get(&timemap_url(&target_url))?;
+        archived_from_timemap(target_url, Provider::ArchiveIs, &resp)
+    }
+}
+
+impl Default for ArchiveClient {
+    fn default() -> Self {
+        ArchiveClient::new("archiveis-rs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn rejects_invalid_urls() {
+        let client = ArchiveClient::default();
+        match client.capture_with_token("not a url", "token") {
+            Err(Error::Url(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match client.lookup("not a url") {
+            Err(Error::Url(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn rejects_filtered_urls() {
+        let client = ArchiveClient::default().filter(LinkFilter::new().reject_private(true));
+        match client.capture_with_token("http://intranet.local/wiki", "token") {
+            Err(Error::Rejected(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
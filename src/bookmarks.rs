@@ -0,0 +1,661 @@
+//! Reading the bookmarks exported by browsers and link saving services.
+//!
+//! Supports the Netscape bookmark html format, which all browsers can export, the JSON formats
+//! of Firefox backups and of the Chrome `Bookmarks` file, the html export of Pocket, the JSON
+//! export of Pinboard and CSV files like the export of Raindrop. It is only available with the
+//! `bookmarks` feature enabled.
+//!
+//! ```
+//! use archiveis::bookmarks::{parse_bookmarks, BookmarkFormat};
+//!
+//! let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+//! <DL><p>
+//!     <DT><H3>Work</H3>
+//!     <DL><p>
+//!         <DT><A HREF="https://example.com/" ADD_DATE="1577836800">Example</A>
+//!     </DL><p>
+//! </DL><p>"#;
+//! let bookmarks = parse_bookmarks(html, BookmarkFormat::Netscape).unwrap();
+//! assert_eq!(bookmarks[0].url.as_str(), "https://example.com/");
+//! assert_eq!(bookmarks[0].title.as_deref(), Some("Example"));
+//! assert_eq!(bookmarks[0].folder, vec!["Work".to_string()]);
+//! ```
+
+use crate::{html, parse, Error, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::{fmt, str::FromStr};
+use url::Url;
+
+/// A bookmarked page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    /// The url of the page
+    pub url: Url,
+    /// The title of the bookmark
+    pub title: Option<String>,
+    /// The names of the folders the bookmark is in, outermost first
+    pub folder: Vec<String>,
+    /// The tags of the bookmark
+    pub tags: Vec<String>,
+    /// When the bookmark was added
+    pub added: Option<DateTime<Utc>>,
+}
+
+impl Bookmark {
+    /// The folders of the bookmark joined with `/`, like `Bookmarks bar/Work`
+    pub fn folder_path(&self) -> String {
+        self.folder.join("/")
+    }
+
+    /// Whether the bookmark is in `folder` or any of its subfolders.
+    ///
+    /// `folder` is a path of folder names separated by `/`, like `Work/Rust`, which may start at
+    /// any level of the folders of the bookmark. Names are compared case insensitive.
+    pub fn in_folder(&self, folder: &str) -> bool {
+        let names: Vec<_> = folder
+            .split('/')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        if names.is_empty() {
+            return true;
+        }
+        self.folder.windows(names.len()).any(|window| {
+            window
+                .iter()
+                .zip(&names)
+                .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+        })
+    }
+}
+
+/// The format of a bookmark export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkFormat {
+    /// The Netscape bookmark html file, exported by all browsers
+    Netscape,
+    /// The JSON backup of Firefox
+    Firefox,
+    /// The JSON `Bookmarks` file of Chrome and Chromium based browsers
+    Chrome,
+    /// The html export of Pocket, with the unread and archived items as folders
+    Pocket,
+    /// The JSON export of Pinboard
+    Pinboard,
+    /// A CSV file with a header line, like the export of Raindrop, see `CsvColumns`
+    Csv,
+}
+
+impl BookmarkFormat {
+    /// Guesses the format from the content of the export
+    pub fn guess(content: &str) -> Option<Self> {
+        let content = content.trim_start();
+        if content.starts_with('{') {
+            if content.contains("\"roots\"") {
+                Some(BookmarkFormat::Chrome)
+            } else {
+                Some(BookmarkFormat::Firefox)
+            }
+        } else if content.starts_with('[') {
+            Some(BookmarkFormat::Pinboard)
+        } else if content.contains("NETSCAPE-Bookmark-file")
+            || content.to_ascii_lowercase().contains("<dl")
+        {
+            Some(BookmarkFormat::Netscape)
+        } else if content.contains("<title>Pocket Export</title>")
+            || content.contains("time_added=")
+        {
+            Some(BookmarkFormat::Pocket)
+        } else if content.lines().next().unwrap_or_default().contains(',') {
+            Some(BookmarkFormat::Csv)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for BookmarkFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookmarkFormat::Netscape => write!(f, "netscape"),
+            BookmarkFormat::Firefox => write!(f, "firefox"),
+            BookmarkFormat::Chrome => write!(f, "chrome"),
+            BookmarkFormat::Pocket => write!(f, "pocket"),
+            BookmarkFormat::Pinboard => write!(f, "pinboard"),
+            BookmarkFormat::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl FromStr for BookmarkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "netscape" | "html" => Ok(BookmarkFormat::Netscape),
+            "firefox" => Ok(BookmarkFormat::Firefox),
+            "chrome" => Ok(BookmarkFormat::Chrome),
+            "pocket" => Ok(BookmarkFormat::Pocket),
+            "pinboard" => Ok(BookmarkFormat::Pinboard),
+            "csv" => Ok(BookmarkFormat::Csv),
+            _ => Err(format!("Unknown bookmark format {}", s)),
+        }
+    }
+}
+
+/// The columns of a CSV file to read bookmarks from.
+///
+/// A column is given by the name in the header line, compared case insensitive, or by its
+/// number starting at 1. By default the columns are named like in the export of Raindrop:
+/// `url`, `title`, `tags`, `folder` and `created`. Tags are separated by commas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvColumns {
+    url: String,
+    title: String,
+    tags: String,
+    folder: String,
+    added: String,
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        CsvColumns {
+            url: "url".to_string(),
+            title: "title".to_string(),
+            tags: "tags".to_string(),
+            folder: "folder".to_string(),
+            added: "created".to_string(),
+        }
+    }
+}
+
+impl CsvColumns {
+    /// The default columns
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The column of the url of the bookmark, which is required
+    pub fn url<T: ToString>(mut self, column: T) -> Self {
+        self.url = column.to_string();
+        self
+    }
+
+    /// The column of the title of the bookmark
+    pub fn title<T: ToString>(mut self, column: T) -> Self {
+        self.title = column.to_string();
+        self
+    }
+
+    /// The column of the comma separated tags of the bookmark
+    pub fn tags<T: ToString>(mut self, column: T) -> Self {
+        self.tags = column.to_string();
+        self
+    }
+
+    /// The column of the folder of the bookmark, with subfolders separated by `/`
+    pub fn folder<T: ToString>(mut self, column: T) -> Self {
+        self.folder = column.to_string();
+        self
+    }
+
+    /// The column of the date the bookmark was added, like `2020-01-01T00:00:00Z`
+    pub fn added<T: ToString>(mut self, column: T) -> Self {
+        self.added = column.to_string();
+        self
+    }
+
+    /// The index of `column` within the `header` line
+    fn index(header: &csv::StringRecord, column: &str) -> Option<usize> {
+        match column.parse::<usize>() {
+            Ok(number) => number.checked_sub(1),
+            Err(_) => header
+                .iter()
+                .position(|name| name.trim().eq_ignore_ascii_case(column)),
+        }
+    }
+}
+
+/// Parses all bookmarks of an export in document order.
+///
+/// Bookmarks of other schemes than http and https, like `javascript:` bookmarklets or the
+/// `place:` queries of Firefox, are skipped. CSV files are read with the default `CsvColumns`,
+/// use `parse_csv` for other columns.
+pub fn parse_bookmarks(content: &str, format: BookmarkFormat) -> Result<Vec<Bookmark>> {
+    let mut bookmarks = match format {
+        BookmarkFormat::Netscape => netscape(content),
+        BookmarkFormat::Pocket => pocket(content),
+        BookmarkFormat::Csv => return parse_csv(content, &CsvColumns::default()),
+        BookmarkFormat::Firefox | BookmarkFormat::Chrome | BookmarkFormat::Pinboard => {
+            let root: Value = serde_json::from_str(content)
+                .map_err(|err| Error::InvalidDocument(err.to_string()))?;
+            let mut bookmarks = Vec::new();
+            if format == BookmarkFormat::Firefox {
+                firefox(&root, &mut Vec::new(), &mut bookmarks);
+            } else if format == BookmarkFormat::Pinboard {
+                let posts = root.as_array().ok_or_else(|| {
+                    Error::InvalidDocument("no array of Pinboard posts".to_string())
+                })?;
+                bookmarks.extend(posts.iter().filter_map(pinboard));
+            } else {
+                let roots = root
+                    .get("roots")
+                    .and_then(Value::as_object)
+                    .ok_or_else(|| {
+                        Error::InvalidDocument("no roots of a Chrome bookmarks file".to_string())
+                    })?;
+                for node in roots.values() {
+                    chrome(node, &mut Vec::new(), &mut bookmarks);
+                }
+            }
+            bookmarks
+        }
+    };
+    bookmarks.retain(is_web);
+    Ok(bookmarks)
+}
+
+/// Parses the bookmarks of a CSV file with a header line.
+///
+/// Rows without a valid http or https url are skipped.
+pub fn parse_csv(content: &str, columns: &CsvColumns) -> Result<Vec<Bookmark>> {
+    let csv_error = |err: csv::Error| Error::InvalidDocument(err.to_string());
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let header = reader.headers().map_err(csv_error)?.clone();
+    let url = CsvColumns::index(&header, &columns.url).ok_or_else(|| {
+        Error::InvalidDocument(format!("no column {} in the CSV header", columns.url))
+    })?;
+    let title = CsvColumns::index(&header, &columns.title);
+    let tags = CsvColumns::index(&header, &columns.tags);
+    let folder = CsvColumns::index(&header, &columns.folder);
+    let added = CsvColumns::index(&header, &columns.added);
+
+    let mut bookmarks = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(csv_error)?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| row.get(column))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let url = match field(Some(url)).and_then(|url| url.parse().ok()) {
+            Some(url) => url,
+            None => continue,
+        };
+        bookmarks.push(Bookmark {
+            url,
+            title: field(title).map(str::to_string),
+            folder: field(folder)
+                .map(|folder| split(folder, '/'))
+                .unwrap_or_default(),
+            tags: field(tags).map(|tags| split(tags, ',')).unwrap_or_default(),
+            added: field(added).and_then(parse::parse_w3c_datetime),
+        });
+    }
+    bookmarks.retain(is_web);
+    Ok(bookmarks)
+}
+
+/// Whether the bookmark is a http or https page
+fn is_web(bookmark: &Bookmark) -> bool {
+    bookmark.url.scheme() == "http" || bookmark.url.scheme() == "https"
+}
+
+/// The non empty parts of a list like the tags `rust, web`
+fn split(list: &str, separator: char) -> Vec<String> {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The bookmarks of a Netscape bookmark file.
+///
+/// Folders are a `<H3>` followed by a `<DL>` list of their content.
+fn netscape(content: &str) -> Vec<Bookmark> {
+    enum Event<'a> {
+        Start(html::Tag<'a>),
+        EndList,
+    }
+    let lower = content.to_ascii_lowercase();
+    let mut events: Vec<(usize, Event)> = html::tags(content)
+        .into_iter()
+        .map(|tag| (tag.range.start, Event::Start(tag)))
+        .chain(
+            lower
+                .match_indices("</dl")
+                .map(|(pos, _)| (pos, Event::EndList)),
+        )
+        .collect();
+    events.sort_by_key(|(pos, _)| *pos);
+
+    let mut bookmarks = Vec::new();
+    // the folders of the open lists, `None` for lists without a heading like the outermost one
+    let mut lists: Vec<Option<String>> = Vec::new();
+    let mut heading = None;
+    for (_, event) in events {
+        match event {
+            Event::EndList => {
+                lists.pop();
+            }
+            Event::Start(tag) => match tag.name.as_str() {
+                "h3" => heading = Some(tag.inner_text()),
+                "dl" => lists.push(heading.take()),
+                "a" => {
+                    let url = match tag.attr("href").and_then(|href| href.parse().ok()) {
+                        Some(url) => url,
+                        None => continue,
+                    };
+                    bookmarks.push(Bookmark {
+                        url,
+                        title: Some(tag.inner_text()).filter(|title| !title.is_empty()),
+                        folder: lists.iter().flatten().cloned().collect(),
+                        tags: tag
+                            .attr("tags")
+                            .map(|tags| split(&tags, ','))
+                            .unwrap_or_default(),
+                        added: tag
+                            .attr("add_date")
+                            .and_then(|date| date.parse().ok())
+                            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+                    });
+                }
+                _ => {}
+            },
+        }
+    }
+    bookmarks
+}
+
+/// The bookmarks of a Pocket export.
+///
+/// The items are listed below a `<h1>` heading like `Unread` or `Read Archive`, which is taken
+/// as their folder.
+fn pocket(content: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    let mut section = None;
+    for tag in html::tags(content) {
+        match tag.name.as_str() {
+            "h1" => section = Some(tag.inner_text()).filter(|section| !section.is_empty()),
+            "a" => {
+                let url = match tag.attr("href").and_then(|href| href.parse().ok()) {
+                    Some(url) => url,
+                    None => continue,
+                };
+                bookmarks.push(Bookmark {
+                    url,
+                    title: Some(tag.inner_text()).filter(|title| !title.is_empty()),
+                    folder: section.iter().cloned().collect(),
+                    tags: tag
+                        .attr("tags")
+                        .map(|tags| split(&tags, ','))
+                        .unwrap_or_default(),
+                    added: tag
+                        .attr("time_added")
+                        .and_then(|date| date.parse().ok())
+                        .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+                });
+            }
+            _ => {}
+        }
+    }
+    bookmarks
+}
+
+/// A post of a Pinboard export
+fn pinboard(post: &Value) -> Option<Bookmark> {
+    let text = |key: &str| {
+        post.get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    Some(Bookmark {
+        url: text("href")?.parse().ok()?,
+        title: text("description").map(str::to_string),
+        folder: Vec::new(),
+        // tags are separated by spaces
+        tags: text("tags")
+            .map(|tags| tags.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        added: text("time").and_then(parse::parse_w3c_datetime),
+    })
+}
+
+/// The bookmarks of a node of a Firefox backup and its children
+fn firefox(node: &Value, folder: &mut Vec<String>, bookmarks: &mut Vec<Bookmark>) {
+    let title = node
+        .get("title")
+        .and_then(Value::as_str)
+        .filter(|title| !title.is_empty());
+    if let Some(url) = node
+        .get("uri")
+        .and_then(Value::as_str)
+        .and_then(|uri| uri.parse().ok())
+    {
+        bookmarks.push(Bookmark {
+            url,
+            title: title.map(str::to_string),
+            folder: folder.clone(),
+            tags: node
+                .get("tags")
+                .and_then(Value::as_str)
+                .map(|tags| split(tags, ','))
+                .unwrap_or_default(),
+            // microseconds since the unix epoch
+            added: node
+                .get("dateAdded")
+                .and_then(Value::as_i64)
+                .and_then(DateTime::from_timestamp_micros),
+        });
+    }
+    if let Some(children) = node.get("children").and_then(Value::as_array) {
+        // the outermost container has no title
+        folder.extend(title.map(str::to_string));
+        for child in children {
+            firefox(child, folder, bookmarks);
+        }
+        if title.is_some() {
+            folder.pop();
+        }
+    }
+}
+
+/// The bookmarks of a node of a Chrome bookmarks file and its children
+fn chrome(node: &Value, folder: &mut Vec<String>, bookmarks: &mut Vec<Bookmark>) {
+    let name = node
+        .get("name")
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty());
+    match node.get("type").and_then(Value::as_str) {
+        Some("url") => {
+            let url = match node
+                .get("url")
+                .and_then(Value::as_str)
+                .and_then(|url| url.parse().ok())
+            {
+                Some(url) => url,
+                None => return,
+            };
+            bookmarks.push(Bookmark {
+                url,
+                title: name.map(str::to_string),
+                folder: folder.clone(),
+                tags: Vec::new(),
+                // microseconds since 1601-01-01, as a string
+                added: node
+                    .get("date_added")
+                    .and_then(Value::as_str)
+                    .and_then(|date| date.parse::<i64>().ok())
+                    .and_then(|micros| {
+                        DateTime::from_timestamp_micros(micros - 11_644_473_600_000_000)
+                    }),
+            });
+        }
+        Some("folder") => {
+            folder.extend(name.map(str::to_string));
+            for child in node
+                .get("children")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                chrome(child, folder, bookmarks);
+            }
+            if name.is_some() {
+                folder.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn netscape_folders() {
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1577836800">Toolbar</H3>
+    <DL><p>
+        <DT><H3>Rust &amp; more</H3>
+        <DL><p>
+            <DT><A HREF="https://www.rust-lang.org/">Rust</A>
+        </DL><p>
+        <DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+        <DT><A HREF="https://example.com/a">A</A>
+    </DL><p>
+    <DT><A HREF="https://example.com/b" ADD_DATE="1577836800"></A>
+</DL><p>"#;
+        assert_eq!(BookmarkFormat::guess(html), Some(BookmarkFormat::Netscape));
+        let bookmarks = parse_bookmarks(html, BookmarkFormat::Netscape).unwrap();
+        let folders: Vec<_> = bookmarks
+            .iter()
+            .map(|bookmark| (bookmark.url.as_str(), bookmark.folder_path()))
+            .collect();
+        assert_eq!(
+            folders,
+            vec![
+                (
+                    "https://www.rust-lang.org/",
+                    "Toolbar/Rust & more".to_string()
+                ),
+                ("https://example.com/a", "Toolbar".to_string()),
+                ("https://example.com/b", String::new()),
+            ]
+        );
+        assert_eq!(bookmarks[2].title, None);
+        assert_eq!(
+            bookmarks[2].added,
+            Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert!(bookmarks[0].in_folder("rust & MORE"));
+        assert!(bookmarks[0].in_folder("Toolbar"));
+        assert!(!bookmarks[1].in_folder("Rust & more"));
+        assert!(!bookmarks[2].in_folder("Toolbar"));
+    }
+
+    #[test]
+    fn json_exports() {
+        let firefox = r#"{"title": "", "type": "text/x-moz-place-container", "children": [
+            {"title": "menu", "type": "text/x-moz-place-container", "children": [
+                {"title": "Example", "type": "text/x-moz-place", "uri": "https://example.com/",
+                 "dateAdded": 1577836800000000, "tags": "rust,web"},
+                {"title": "Recent", "type": "text/x-moz-place", "uri": "place:sort=8"}
+            ]}
+        ]}"#;
+        assert_eq!(
+            BookmarkFormat::guess(firefox),
+            Some(BookmarkFormat::Firefox)
+        );
+        assert_eq!(
+            parse_bookmarks(firefox, BookmarkFormat::Firefox).unwrap(),
+            vec![Bookmark {
+                url: "https://example.com/".parse().unwrap(),
+                title: Some("Example".to_string()),
+                folder: vec!["menu".to_string()],
+                tags: vec!["rust".to_string(), "web".to_string()],
+                added: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+            }]
+        );
+
+        let chrome = r#"{"roots": {"bookmark_bar": {"name": "Bookmarks bar", "type": "folder",
+            "children": [{"name": "Work", "type": "folder", "children": [
+                {"name": "Example", "type": "url", "url": "https://example.com/",
+                 "date_added": "13222310400000000"}
+            ]}]}}, "version": 1}"#;
+        assert_eq!(BookmarkFormat::guess(chrome), Some(BookmarkFormat::Chrome));
+        let bookmarks = parse_bookmarks(chrome, BookmarkFormat::Chrome).unwrap();
+        assert_eq!(bookmarks[0].folder_path(), "Bookmarks bar/Work");
+        assert_eq!(
+            bookmarks[0].added,
+            Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert!(parse_bookmarks("[]", BookmarkFormat::Chrome).is_err());
+    }
+
+    #[test]
+    fn service_exports() {
+        let pocket = r#"<!DOCTYPE html>
+<html><head><title>Pocket Export</title></head><body>
+<h1>Unread</h1>
+<ul>
+<li><a href="https://example.com/unread" time_added="1577836800" tags="rust,later">Unread</a></li>
+</ul>
+<h1>Read Archive</h1>
+<ul>
+<li><a href="https://example.com/read" time_added="1577836800" tags="">https://example.com/read</a></li>
+</ul>
+</body></html>"#;
+        assert_eq!(BookmarkFormat::guess(pocket), Some(BookmarkFormat::Pocket));
+        let bookmarks = parse_bookmarks(pocket, BookmarkFormat::Pocket).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].tags, vec!["rust", "later"]);
+        assert_eq!(bookmarks[0].folder, vec!["Unread"]);
+        assert_eq!(bookmarks[1].folder_path(), "Read Archive");
+        assert!(bookmarks[1].tags.is_empty());
+
+        let pinboard = r#"[{"href": "https://example.com/", "description": "Example",
+            "extended": "", "time": "2020-01-01T00:00:00Z", "tags": "rust web"}]"#;
+        assert_eq!(
+            BookmarkFormat::guess(pinboard),
+            Some(BookmarkFormat::Pinboard)
+        );
+        assert_eq!(
+            parse_bookmarks(pinboard, BookmarkFormat::Pinboard).unwrap(),
+            vec![Bookmark {
+                url: "https://example.com/".parse().unwrap(),
+                title: Some("Example".to_string()),
+                folder: Vec::new(),
+                tags: vec!["rust".to_string(), "web".to_string()],
+                added: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+            }]
+        );
+
+        let raindrop = "id,title,note,excerpt,url,folder,tags,created
+1,\"Example, Inc.\",,,https://example.com/,Work/Rust,\"rust, web\",2020-01-01T00:00:00.000Z
+2,Broken,,,not an url,,,
+";
+        assert_eq!(BookmarkFormat::guess(raindrop), Some(BookmarkFormat::Csv));
+        let bookmarks = parse_bookmarks(raindrop, BookmarkFormat::Csv).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Example, Inc."));
+        assert_eq!(bookmarks[0].folder_path(), "Work/Rust");
+        assert_eq!(bookmarks[0].tags, vec!["rust", "web"]);
+
+        let links = "Link,Name\nhttps://example.com/,Example\n";
+        let bookmarks = parse_csv(links, &CsvColumns::new().url("link").title(2)).unwrap();
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Example"));
+        assert!(parse_csv("name\nexample", &CsvColumns::new()).is_err());
+    }
+}
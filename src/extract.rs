@@ -0,0 +1,538 @@
+//! Extracting links from html, Markdown and plain text documents.
+//!
+//! ```
+//! use archiveis::extract::{DocumentFormat, Extractor};
+//!
+//! let markdown = "See [the docs](https://docs.rs/archiveis) and https://example.com/page.";
+//! let urls = Extractor::new()
+//!     .exclude_domain("docs.rs")
+//!     .urls(markdown, DocumentFormat::Markdown);
+//! assert_eq!(urls, vec!["https://example.com/page".parse().unwrap()]);
+//! ```
+
+use crate::html;
+use std::{fmt, ops::Range, path::Path, str::FromStr};
+use url::Url;
+
+/// The format of a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentFormat {
+    /// Links are read from the `href` and `src` attributes of all tags
+    Html,
+    /// Links are read from inline links, reference definitions, autolinks and the plain text
+    Markdown,
+    /// Every `http://` or `https://` url in the text is a link
+    #[default]
+    Text,
+}
+
+impl DocumentFormat {
+    /// Guesses the format of a document from the extension of its `path`, or from its content if
+    /// the extension is unknown.
+    pub fn guess(path: Option<&Path>, document: &str) -> Self {
+        let extension = path
+            .and_then(Path::extension)
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("html") | Some("htm") | Some("xhtml") => DocumentFormat::Html,
+            Some("md") | Some("markdown") | Some("mdown") => DocumentFormat::Markdown,
+            Some("txt") => DocumentFormat::Text,
+            _ => {
+                let start = document.trim_start().to_lowercase();
+                if start.starts_with("<!doctype html") || start.starts_with("<html") {
+                    DocumentFormat::Html
+                } else {
+                    DocumentFormat::Text
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for DocumentFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocumentFormat::Html => write!(f, "html"),
+            DocumentFormat::Markdown => write!(f, "markdown"),
+            DocumentFormat::Text => write!(f, "text"),
+        }
+    }
+}
+
+impl FromStr for DocumentFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "html" | "htm" => Ok(DocumentFormat::Html),
+            "markdown" | "md" => Ok(DocumentFormat::Markdown),
+            "text" | "txt" => Ok(DocumentFormat::Text),
+            _ => Err(format!("Unknown document format {}", s)),
+        }
+    }
+}
+
+/// A link within a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// The absolute url of the link
+    pub url: Url,
+    /// The position of the link as written in the document
+    pub range: Range<usize>,
+}
+
+/// Extracts the `http` and `https` links of documents.
+///
+/// By default every link is extracted, `include_domain` and `exclude_domain` restrict the links
+/// to certain domains and their subdomains.
+#[derive(Debug, Clone, Default)]
+pub struct Extractor {
+    /// The url relative links are resolved against
+    base: Option<Url>,
+    /// If not empty, only links to these domains are extracted
+    include: Vec<String>,
+    /// Links to these domains are never extracted
+    exclude: Vec<String>,
+}
+
+impl Extractor {
+    /// Creates a new `Extractor` that extracts every absolute link
+    pub fn new() -> Self {
+        Extractor::default()
+    }
+
+    /// Resolves relative links against `base`, without a base relative links are skipped.
+    ///
+    /// A `<base href>` tag in a html document takes precedence.
+    pub fn base(mut self, base: Url) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    /// Only extracts links to `domain` and its subdomains, and to other included domains
+    pub fn include_domain<T: ToString>(mut self, domain: T) -> Self {
+        self.include.push(normalize_domain(&domain.to_string()));
+        self
+    }
+
+    /// Skips all links to `domain` and its subdomains
+    pub fn exclude_domain<T: ToString>(mut self, domain: T) -> Self {
+        self.exclude.push(normalize_domain(&domain.to_string()));
+        self
+    }
+
+    /// Whether a link to `url` is extracted
+    pub fn allows(&self, url: &Url) -> bool {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return false;
+        }
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return false,
+        };
+        (self.include.is_empty() || self.include.iter().any(|d| is_subdomain(host, d)))
+            && !self.exclude.iter().any(|d| is_subdomain(host, d))
+    }
+
+    /// All links of the document in the order they appear, including duplicates
+    pub fn links(&self, document: &str, format: DocumentFormat) -> Vec<Link> {
+        let links = match format {
+            DocumentFormat::Html => self.html_links(document),
+            DocumentFormat::Markdown => markdown_links(document)
+                .into_iter()
+                .filter_map(|range| {
+                    let url = resolve(self.base.as_ref(), &document[range.clone()])?;
+                    Some(Link { url, range })
+                })
+                .collect(),
+            DocumentFormat::Text => bare_urls(document)
+                .into_iter()
+                .filter_map(|range| {
+                    let url = document[range.clone()].parse().ok()?;
+                    Some(Link { url, range })
+                })
+                .collect(),
+        };
+        links
+            .into_iter()
+            .filter(|link| self.allows(&link.url))
+            .collect()
+    }
+
+    /// The unique urls of all links of the document in the order they first appear
+    pub fn urls(&self, document: &str, format: DocumentFormat) -> Vec<Url> {
+        let mut urls: Vec<Url> = Vec::new();
+        for link in self.links(document, format) {
+            if !urls.contains(&link.url) {
+                urls.push(link.url);
+            }
+        }
+        urls
+    }
+
+    /// The `href` and `src` attributes of all tags
+    fn html_links(&self, document: &str) -> Vec<Link> {
+        let tags = html::tags(document);
+        let base = tags
+            .iter()
+            .find(|tag| tag.name == "base")
+            .and_then(|tag| tag.attr("href"))
+            .and_then(|href| resolve(self.base.as_ref(), &href))
+            .or_else(|| self.base.clone());
+        let mut links = Vec::new();
+        for tag in &tags {
+            if tag.name == "base" {
+                continue;
+            }
+            for name in &["href", "src"] {
+                if let Some((range, value)) = tag.attr_range(name) {
+                    let value = html::decode_entities(value);
+                    let value = value.trim();
+                    // links to an anchor of the same page
+                    if value.starts_with('#') {
+                        continue;
+                    }
+                    if let Some(url) = resolve(base.as_ref(), value) {
+                        links.push(Link { url, range });
+                    }
+                }
+            }
+        }
+        links
+    }
+}
+
+/// Lowercases `domain` and strips a leading `*.` or `.`
+fn normalize_domain(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_start_matches('.')
+        .to_lowercase()
+}
+
+/// Whether `host` is `domain` or one of its subdomains
+fn is_subdomain(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.len() > domain.len()
+            && host.ends_with(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.')
+}
+
+/// Parses `link`, resolving it against `base` if there is one
+fn resolve(base: Option<&Url>, link: &str) -> Option<Url> {
+    match base {
+        Some(base) => base.join(link).ok(),
+        None => Url::parse(link).ok(),
+    }
+}
+
+/// The positions of the destinations of all links of a Markdown document.
+///
+/// Inline links `[text](url)`, reference definitions `[id]: url`, autolinks `<url>` and bare
+/// urls in the text are links, except within code spans and fenced code blocks.
+fn markdown_links(document: &str) -> Vec<Range<usize>> {
+    let mut links = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in document.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        links.extend(
+            markdown_line_links(line)
+                .into_iter()
+                .map(|range| start + range.start..start + range.end),
+        );
+    }
+    links
+}
+
+/// The links of a single line of Markdown outside of a code block
+fn markdown_line_links(line: &str) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    // parts of the line that can't contain bare urls
+    let mut occupied = code_spans(line);
+    let mut links = Vec::new();
+
+    // reference definition
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent <= 3 && line[indent..].starts_with('[') {
+        if let Some(end) = line[indent..].find("]:").map(|i| indent + i + 2) {
+            let dest = line[end..].trim_start();
+            let dest_start = line.len() - dest.len();
+            let len = dest.find(char::is_whitespace).unwrap_or(dest.len());
+            let dest = dest_start..dest_start + len;
+            links.push(strip_angle_brackets(line, dest));
+            occupied.push(0..line.len());
+        }
+    }
+
+    // inline links
+    let mut pos = 0;
+    while let Some(open) = line[pos..].find("](").map(|i| pos + i) {
+        pos = open + 2;
+        if in_ranges(&occupied, open) {
+            continue;
+        }
+        let mut start = pos;
+        while start < bytes.len() && bytes[start] == b' ' {
+            start += 1;
+        }
+        let end = if bytes.get(start) == Some(&b'<') {
+            match line[start..].find('>') {
+                Some(i) => start + i + 1,
+                None => continue,
+            }
+        } else {
+            let mut depth = 0;
+            let mut end = start;
+            while end < bytes.len() && !bytes[end].is_ascii_whitespace() {
+                match bytes[end] {
+                    b'(' => depth += 1,
+                    b')' if depth == 0 => break,
+                    b')' => depth -= 1,
+                    _ => {}
+                }
+                end += 1;
+            }
+            end
+        };
+        if end > start {
+            links.push(strip_angle_brackets(line, start..end));
+        }
+        // the link text and destination
+        let text_start = line[..open].rfind('[').unwrap_or(open);
+        occupied.push(text_start..end + 1);
+        pos = end;
+    }
+
+    // autolinks
+    let mut pos = 0;
+    while let Some(open) = line[pos..].find('<').map(|i| pos + i) {
+        pos = open + 1;
+        let rest = &line[pos..];
+        if in_ranges(&occupied, open) || !starts_with_scheme(rest) {
+            continue;
+        }
+        if let Some(len) = rest.find(|c: char| c == '>' || c.is_whitespace()) {
+            if rest[len..].starts_with('>') {
+                links.push(pos..pos + len);
+                occupied.push(open..pos + len + 1);
+                pos += len + 1;
+            }
+        }
+    }
+
+    links.extend(
+        bare_urls(line)
+            .into_iter()
+            .filter(|range| !in_ranges(&occupied, range.start)),
+    );
+    links.sort_by_key(|range| range.start);
+    links
+}
+
+/// The positions of all inline code spans of a line
+fn code_spans(line: &str) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    let run = |pos: usize| bytes[pos..].iter().take_while(|&&b| b == b'`').count();
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = line[pos..].find('`').map(|i| pos + i) {
+        let len = run(start);
+        pos = start + len;
+        // the closing run needs to have the same length
+        let mut search = pos;
+        while let Some(end) = line[search..].find('`').map(|i| search + i) {
+            let end_len = run(end);
+            if end_len == len {
+                spans.push(start..end + end_len);
+                pos = end + end_len;
+                break;
+            }
+            search = end + end_len;
+        }
+    }
+    spans
+}
+
+/// Removes the `<` and `>` around a link destination
+fn strip_angle_brackets(line: &str, range: Range<usize>) -> Range<usize> {
+    let dest = &line[range.clone()];
+    if dest.len() >= 2 && dest.starts_with('<') && dest.ends_with('>') {
+        range.start + 1..range.end - 1
+    } else {
+        range
+    }
+}
+
+/// Whether `pos` lies within any of the ranges
+fn in_ranges(ranges: &[Range<usize>], pos: usize) -> bool {
+    ranges.iter().any(|range| range.contains(&pos))
+}
+
+/// Whether `text` starts with `http://` or `https://`, ignoring case
+fn starts_with_scheme(text: &str) -> bool {
+    ["http://", "https://"].iter().any(|scheme| {
+        text.len() >= scheme.len()
+            && text.as_bytes()[..scheme.len()].eq_ignore_ascii_case(scheme.as_bytes())
+    })
+}
+
+/// The positions of all `http://` and `https://` urls within a text.
+///
+/// A url ends at whitespace or a character that isn't allowed in urls. Punctuation at the end,
+/// like the full stop of a sentence, and closing brackets without an opening one are not part of
+/// the url.
+fn bare_urls(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = match text[pos..]
+            .char_indices()
+            .find(|&(i, c)| (c == 'h' || c == 'H') && starts_with_scheme(&text[pos + i..]))
+        {
+            Some((i, _)) => pos + i,
+            None => break,
+        };
+        let mut end = start;
+        while end < bytes.len()
+            && !bytes[end].is_ascii_whitespace()
+            && !matches!(
+                bytes[end],
+                b'<' | b'>' | b'"' | b'\'' | b'`' | b'{' | b'}' | b'|' | b'\\' | b'^'
+            )
+        {
+            end += 1;
+        }
+        pos = end.max(start + 1);
+        // a url that is part of a word, like `xhttp://`
+        if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+        while end > start {
+            let url = &text[start..end];
+            let trailing = url.as_bytes()[url.len() - 1];
+            let unbalanced =
+                |open: char, close: char| url.matches(close).count() > url.matches(open).count();
+            match trailing {
+                b'.' | b',' | b';' | b':' | b'!' | b'?' | b'*' | b'_' | b'~' => end -= 1,
+                b')' if unbalanced('(', ')') => end -= 1,
+                b']' if unbalanced('[', ']') => end -= 1,
+                _ => break,
+            }
+        }
+        // only the scheme
+        if text[start..end].ends_with("//") {
+            continue;
+        }
+        urls.push(start..end);
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(document: &str, format: DocumentFormat) -> Vec<String> {
+        Extractor::new()
+            .links(document, format)
+            .into_iter()
+            .map(|link| {
+                assert!(!document[link.range.clone()].is_empty());
+                link.url.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn html_links() {
+        let html = r##"<html><head><link rel="stylesheet" href="/style.css"></head>
+<body><a href="https://example.com/?a=1&amp;b=2">x</a><a href="#top">top</a>
+<a href="mailto:me@example.com">mail</a><img src="http://example.org/img.png"></body></html>"##;
+        assert_eq!(
+            urls(html, DocumentFormat::Html),
+            vec!["https://example.com/?a=1&b=2", "http://example.org/img.png"]
+        );
+        let links = Extractor::new()
+            .base("https://example.net/dir/page.html".parse().unwrap())
+            .links(html, DocumentFormat::Html);
+        assert_eq!(links[0].url.as_str(), "https://example.net/style.css");
+        assert_eq!(&html[links[0].range.clone()], "/style.css");
+    }
+
+    #[test]
+    fn markdown_links() {
+        let markdown = r#"# Links
+An [inline](https://example.com/wiki/Rust_(language)) link, [with title](<https://example.com/a b> "title")
+and an autolink <https://example.org/auto>, a bare one https://example.net/bare.
+`https://example.com/code` and [a reference][ref]
+
+```
+https://example.com/fenced
+```
+[ref]: https://example.com/ref "Title"
+"#;
+        assert_eq!(
+            urls(markdown, DocumentFormat::Markdown),
+            vec![
+                "https://example.com/wiki/Rust_(language)",
+                "https://example.com/a%20b",
+                "https://example.org/auto",
+                "https://example.net/bare",
+                "https://example.com/ref",
+            ]
+        );
+    }
+
+    #[test]
+    fn text_links() {
+        let text = "Visit https://example.com/page, (see http://example.org/a_(b)) or xhttp://no.\nhttps://";
+        assert_eq!(
+            urls(text, DocumentFormat::Text),
+            vec!["https://example.com/page", "http://example.org/a_(b)"]
+        );
+    }
+
+    #[test]
+    fn domain_filters() {
+        let text = "https://example.com/ https://www.example.com/ https://notexample.com/ https://docs.rs/";
+        let extractor = Extractor::new()
+            .include_domain("example.com")
+            .include_domain("docs.rs")
+            .exclude_domain("*.www.example.com");
+        let urls: Vec<_> = extractor
+            .urls(text, DocumentFormat::Text)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(urls, vec!["https://example.com/", "https://docs.rs/"]);
+    }
+
+    #[test]
+    fn guess_format() {
+        assert_eq!(
+            DocumentFormat::guess(Some(Path::new("README.md")), ""),
+            DocumentFormat::Markdown
+        );
+        assert_eq!(
+            DocumentFormat::guess(None, "  <!DOCTYPE html><html>"),
+            DocumentFormat::Html
+        );
+        assert_eq!(DocumentFormat::guess(None, "links"), DocumentFormat::Text);
+    }
+}
@@ -0,0 +1,299 @@
+//! Reading the entries of RSS and Atom feeds.
+//!
+//! Supports RSS 2.0 (and the older RSS versions with the same `<item>` layout) and Atom 1.0.
+//! Together with a `FeedStore` only the entries that weren't captured before are archived.
+//!
+//! ```no_run
+//! # use archiveis::feed::FeedClient;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = FeedClient::default();
+//! let feed = client.fetch(&"https://example.com/atom.xml".parse()?).await?;
+//! for entry in feed.entries {
+//!     println!("{} {}", entry.id, entry.link);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use crate::transport;
+use crate::{html, parse, store::SeenEntry, Error, Result};
+use chrono::{DateTime, Utc};
+use url::Url;
+
+/// An entry of a feed, an `<item>` of RSS or an `<entry>` of Atom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    /// The unique id of the entry, its `<guid>` or `<id>`, or its link if it has none
+    pub id: String,
+    /// The link to the page of the entry
+    pub link: Url,
+    /// The title of the entry
+    pub title: Option<String>,
+    /// When the entry was last updated, or published if it was never updated
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl FeedEntry {
+    /// Whether the entry should be captured, given how it was `seen` by a previous run.
+    ///
+    /// An entry is new if it wasn't seen before, or if it was updated since.
+    pub fn is_new(&self, seen: Option<&SeenEntry>) -> bool {
+        match seen.map(|seen| (self.updated, seen.updated)) {
+            None => true,
+            Some((Some(updated), Some(seen))) => updated > seen,
+            Some(_) => false,
+        }
+    }
+}
+
+/// A parsed RSS or Atom feed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    /// The title of the feed
+    pub title: Option<String>,
+    /// All entries of the feed that have a valid link
+    pub entries: Vec<FeedEntry>,
+}
+
+/// The fields of an entry while it is parsed
+#[derive(Default)]
+struct Fields {
+    id: Option<String>,
+    link: Option<Url>,
+    /// the `<guid>` of RSS, if it is a link
+    permalink: Option<Url>,
+    title: Option<String>,
+    updated: Option<DateTime<Utc>>,
+    published: Option<DateTime<Utc>>,
+}
+
+impl Fields {
+    fn into_entry(self) -> Option<FeedEntry> {
+        let link = self.link.or(self.permalink)?;
+        Some(FeedEntry {
+            id: self.id.unwrap_or_else(|| link.to_string()),
+            link,
+            title: self.title,
+            updated: self.updated.or(self.published),
+        })
+    }
+}
+
+impl Feed {
+    /// Parses the xml of a RSS or Atom feed.
+    ///
+    /// Relative links are resolved against `base`, usually the url of the feed. Entries
+    /// without a valid link are skipped.
+    pub fn parse(xml: &str, base: Option<&Url>) -> Result<Self> {
+        let tags = html::tags(xml);
+        let entry = match tags
+            .iter()
+            .map(|tag| tag.local_name())
+            .find(|name| matches!(*name, "rss" | "rdf" | "feed"))
+        {
+            Some("feed") => "entry",
+            Some(_) => "item",
+            None => {
+                return Err(Error::InvalidDocument(
+                    "no <rss> or Atom <feed> found".to_string(),
+                ))
+            }
+        };
+        let parse_url = |url: &str| match base {
+            Some(base) => base.join(url).ok(),
+            None => url.parse().ok(),
+        };
+
+        let mut title = None;
+        let mut entries = Vec::new();
+        let mut current: Option<Fields> = None;
+        for tag in &tags {
+            let name = tag.local_name();
+            if name == entry {
+                entries.extend(current.take().and_then(Fields::into_entry));
+                current = Some(Fields::default());
+                continue;
+            }
+            let fields = match &mut current {
+                Some(fields) => fields,
+                None => {
+                    // the title of the channel or feed itself
+                    if name == "title" && title.is_none() {
+                        title = Some(tag.inner_text()).filter(|title| !title.is_empty());
+                    }
+                    continue;
+                }
+            };
+            // nested elements like the `<source>` of an Atom entry come after the fields of
+            // the entry itself, so the first value wins
+            match name {
+                "guid" | "id" if fields.id.is_none() => {
+                    fields.id = Some(tag.inner_text()).filter(|id| !id.is_empty());
+                    // a permalink guid is the link of an item without `<link>`
+                    if name == "guid" && tag.attr("ispermalink").as_deref() != Some("false") {
+                        fields.permalink = fields
+                            .id
+                            .as_deref()
+                            .and_then(parse_url)
+                            .filter(|url| url.scheme() == "http" || url.scheme() == "https");
+                    }
+                }
+                "link" if fields.link.is_none() => {
+                    fields.link = match tag.attr("href") {
+                        // Atom: only the alternate representation is the page of the entry
+                        Some(href) => match tag.attr("rel").as_deref() {
+                            None | Some("alternate") => parse_url(&href),
+                            _ => None,
+                        },
+                        None => parse_url(&tag.inner_text()),
+                    };
+                }
+                "title" if fields.title.is_none() => {
+                    fields.title = Some(tag.inner_text()).filter(|title| !title.is_empty());
+                }
+                "updated" | "modified" if fields.updated.is_none() => {
+                    fields.updated = parse_date(&tag.inner_text());
+                }
+                "pubdate" | "published" | "issued" | "date" if fields.published.is_none() => {
+                    fields.published = parse_date(&tag.inner_text());
+                }
+                _ => {}
+            }
+        }
+        entries.extend(current.and_then(Fields::into_entry));
+        Ok(Feed { title, entries })
+    }
+}
+
+/// Parses the RFC 2822 dates of RSS and the RFC 3339 dates of Atom
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| parse::parse_w3c_datetime(value))
+}
+
+/// Fetches feeds
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+pub struct FeedClient {
+    /// The internal Http Client of the selected runtime.
+    client: transport::HttpClient,
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+impl FeedClient {
+    /// Creates a new instance of the `FeedClient` using a special user agent
+    pub fn new<T: ToString>(user_agent: T) -> Self {
+        FeedClient {
+            client: transport::HttpClient::new(&user_agent.to_string()),
+        }
+    }
+
+    /// Fetches and parses the feed at `url`
+    pub async fn fetch(&self, url: &Url) -> Result<Feed> {
+        let resp = self.client.get(url.as_str()).await?;
+        if !resp.is_success() {
+            error!("Failed to fetch feed {}: status {}", url, resp.status);
+            return Err(Error::ServerError(url.to_string()));
+        }
+        Feed::parse(&String::from_utf8_lossy(&resp.body), Some(url))
+    }
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+impl Default for FeedClient {
+    fn default() -> Self {
+        FeedClient::new("archiveis-rs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parse_rss() {
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>News</title>
+    <link>https://example.com/</link>
+    <atom:link href="https://example.com/rss.xml" rel="self" type="application/rss+xml"/>
+    <item>
+      <title>First &amp; foremost</title>
+      <link>https://example.com/first</link>
+      <guid isPermaLink="false">post-1</guid>
+      <pubDate>Sat, 07 Sep 2002 09:42:31 GMT</pubDate>
+      <description><![CDATA[<p>Some <b>text</b></p>]]></description>
+    </item>
+    <item>
+      <guid>https://example.com/second</guid>
+    </item>
+    <item><title>No link</title></item>
+  </channel>
+</rss>"#;
+        let feed = Feed::parse(rss, None).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("News"));
+        assert_eq!(
+            feed.entries,
+            vec![
+                FeedEntry {
+                    id: "post-1".to_string(),
+                    link: "https://example.com/first".parse().unwrap(),
+                    title: Some("First & foremost".to_string()),
+                    updated: Some(Utc.with_ymd_and_hms(2002, 9, 7, 9, 42, 31).unwrap()),
+                },
+                FeedEntry {
+                    id: "https://example.com/second".to_string(),
+                    link: "https://example.com/second".parse().unwrap(),
+                    title: None,
+                    updated: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_atom() {
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title type="text">Blog</title>
+  <entry>
+    <title>Post</title>
+    <link rel="edit" href="/edit/1"/>
+    <link href="/posts/1"/>
+    <id>tag:example.com,2024:1</id>
+    <published>2024-01-01T00:00:00Z</published>
+    <updated>2024-02-01T12:00:00+01:00</updated>
+    <source><id>tag:other</id><title>Other</title></source>
+  </entry>
+</feed>"#;
+        let base = "https://example.com/atom.xml".parse().unwrap();
+        let feed = Feed::parse(atom, Some(&base)).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Blog"));
+        let entry = &feed.entries[0];
+        assert_eq!(entry.id, "tag:example.com,2024:1");
+        assert_eq!(entry.link.as_str(), "https://example.com/posts/1");
+        assert_eq!(entry.title.as_deref(), Some("Post"));
+        assert_eq!(
+            entry.updated,
+            Some(Utc.with_ymd_and_hms(2024, 2, 1, 11, 0, 0).unwrap())
+        );
+
+        let seen = |updated| SeenEntry {
+            feed_url: base.to_string(),
+            entry_id: entry.id.clone(),
+            updated,
+            time_stamp: Utc::now(),
+        };
+        assert!(entry.is_new(None));
+        assert!(!entry.is_new(Some(&seen(entry.updated))));
+        assert!(entry.is_new(Some(&seen(Some(
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+        )))));
+        assert!(Feed::parse("<html></html>", None).is_err());
+    }
+}
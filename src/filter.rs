@@ -0,0 +1,367 @@
+//! Deciding which urls may be submitted to the archive services.
+//!
+//! A `LinkFilter` rejects urls by regular expressions, by their domain and, to never submit
+//! intranet urls to a public archive, by pointing to localhost or a private network. It is only
+//! available with the `filter` feature enabled.
+//!
+//! ```
+//! use archiveis::filter::{LinkFilter, Regex, Rejection};
+//! use url::Url;
+//!
+//! let filter = LinkFilter::new()
+//!     .deny_domain("corp.example.com")
+//!     .exclude(Regex::new(r"\.pdf$").unwrap())
+//!     .reject_private(true);
+//! let urls: Vec<Url> = [
+//!     "https://example.com/",
+//!     "https://wiki.corp.example.com/",
+//!     "https://example.com/paper.pdf",
+//!     "http://192.168.1.1/admin",
+//! ]
+//! .iter()
+//! .map(|url| url.parse().unwrap())
+//! .collect();
+//! let summary = filter.partition(urls);
+//! assert_eq!(summary.accepted.len(), 1);
+//! assert_eq!(summary.rejected[2].1, Rejection::Private);
+//! ```
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+use url::{Host, Url};
+
+pub use regex::Regex;
+
+/// The top level domains that are reserved for local networks
+const PRIVATE_DOMAINS: &[&str] = &[
+    "localhost",
+    "local",
+    "internal",
+    "intranet",
+    "lan",
+    "home.arpa",
+];
+
+/// Why a `LinkFilter` rejected an url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// The url points to localhost, a private network or a host without a public domain
+    Private,
+    /// The domain of the url matches this denied domain pattern
+    DeniedDomain(String),
+    /// The domain of the url matches none of the allowed domain patterns
+    NotAllowedDomain,
+    /// The url matches this excluding regular expression
+    Excluded(String),
+    /// The url matches none of the including regular expressions
+    NotIncluded,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Private => write!(f, "points to localhost or a private network"),
+            Rejection::DeniedDomain(domain) => write!(f, "is on the denied domain {}", domain),
+            Rejection::NotAllowedDomain => write!(f, "is on none of the allowed domains"),
+            Rejection::Excluded(pattern) => write!(f, "matches the excluded pattern {}", pattern),
+            Rejection::NotIncluded => write!(f, "matches none of the included patterns"),
+        }
+    }
+}
+
+/// The urls a `LinkFilter` accepted and rejected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// The accepted urls in the order they were given
+    pub accepted: Vec<Url>,
+    /// The rejected urls in the order they were given, together with the reason
+    pub rejected: Vec<(Url, Rejection)>,
+}
+
+/// Accepts or rejects urls.
+///
+/// By default every url is accepted. Domain patterns match the domain and all its subdomains,
+/// like the domains of an `extract::Extractor`, and may contain `*` as wildcard for any part of
+/// the host, like `intranet-*.example.com` or `10.*`. Regular expressions are matched against
+/// the whole url. The rules are checked in the order private, denied domains, allowed domains,
+/// excludes and includes, the first rule that rejects the url is its `Rejection`.
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    /// If not empty, only urls matching one of these are accepted
+    include: Vec<Regex>,
+    /// Urls matching one of these are rejected
+    exclude: Vec<Regex>,
+    /// If not empty, only urls to these domains are accepted
+    allow_domains: Vec<String>,
+    /// Urls to these domains are rejected
+    deny_domains: Vec<String>,
+    /// Whether urls to localhost and private networks are rejected
+    reject_private: bool,
+}
+
+impl LinkFilter {
+    /// Creates a new `LinkFilter` that accepts every url
+    pub fn new() -> Self {
+        LinkFilter::default()
+    }
+
+    /// Only accepts urls that match `regex`, or another included regular expression
+    pub fn include(mut self, regex: Regex) -> Self {
+        self.include.push(regex);
+        self
+    }
+
+    /// Rejects all urls that match `regex`
+    pub fn exclude(mut self, regex: Regex) -> Self {
+        self.exclude.push(regex);
+        self
+    }
+
+    /// Only accepts urls to domains matching `pattern`, or another allowed domain pattern
+    pub fn allow_domain<T: ToString>(mut self, pattern: T) -> Self {
+        self.allow_domains
+            .push(normalize_pattern(&pattern.to_string()));
+        self
+    }
+
+    /// Rejects all urls to domains matching `pattern`
+    pub fn deny_domain<T: ToString>(mut self, pattern: T) -> Self {
+        self.deny_domains
+            .push(normalize_pattern(&pattern.to_string()));
+        self
+    }
+
+    /// Whether urls to localhost, private and link-local ip addresses, hosts without a dot and
+    /// reserved local domains like `.local` or `.internal` are rejected
+    pub fn reject_private(mut self, reject: bool) -> Self {
+        self.reject_private = reject;
+        self
+    }
+
+    /// Checks `url` against all rules
+    pub fn check(&self, url: &Url) -> Result<(), Rejection> {
+        let host = url.host();
+        let private = match &host {
+            Some(host) => is_private(host),
+            None => true,
+        };
+        if self.reject_private && private {
+            return Err(Rejection::Private);
+        }
+        let domain = url
+            .host_str()
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_lowercase();
+        if let Some(pattern) = self
+            .deny_domains
+            .iter()
+            .find(|pattern| matches_domain(&domain, pattern))
+        {
+            return Err(Rejection::DeniedDomain(pattern.clone()));
+        }
+        if !self.allow_domains.is_empty()
+            && !self
+                .allow_domains
+                .iter()
+                .any(|pattern| matches_domain(&domain, pattern))
+        {
+            return Err(Rejection::NotAllowedDomain);
+        }
+        if let Some(regex) = self
+            .exclude
+            .iter()
+            .find(|regex| regex.is_match(url.as_str()))
+        {
+            return Err(Rejection::Excluded(regex.to_string()));
+        }
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|regex| regex.is_match(url.as_str()))
+        {
+            return Err(Rejection::NotIncluded);
+        }
+        Ok(())
+    }
+
+    /// Whether `url` passes all rules
+    pub fn accepts(&self, url: &Url) -> bool {
+        self.check(url).is_ok()
+    }
+
+    /// Checks all `urls`, to show what would be submitted
+    pub fn partition<I: IntoIterator<Item = Url>>(&self, urls: I) -> Summary {
+        let mut summary = Summary::default();
+        for url in urls {
+            match self.check(&url) {
+                Ok(()) => summary.accepted.push(url),
+                Err(rejection) => summary.rejected.push((url, rejection)),
+            }
+        }
+        summary
+    }
+}
+
+/// Lowercases the domain `pattern` and strips a leading `*.` or `.`
+fn normalize_pattern(pattern: &str) -> String {
+    let pattern = pattern.trim().trim_end_matches('.').to_lowercase();
+    pattern
+        .strip_prefix("*.")
+        .or_else(|| pattern.strip_prefix('.'))
+        .unwrap_or(&pattern)
+        .to_string()
+}
+
+/// Whether `domain` or one of its parent domains matches the glob `pattern`
+fn matches_domain(domain: &str, pattern: &str) -> bool {
+    std::iter::once(domain)
+        .chain(domain.match_indices('.').map(|(pos, _)| &domain[pos + 1..]))
+        .any(|domain| glob_match(domain, pattern))
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any sequence of characters
+fn glob_match(text: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<_> = parts.collect();
+    match parts.split_last() {
+        // no wildcard at all
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(pos) => rest = &rest[pos + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.len() >= last.len() && rest.ends_with(last)
+        }
+    }
+}
+
+/// Whether `host` is localhost or in a private network
+fn is_private(host: &Host<&str>) -> bool {
+    match host {
+        Host::Ipv4(ip) => is_private_v4(ip),
+        Host::Ipv6(ip) => is_private_v6(ip),
+        Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            !domain.contains('.')
+                || PRIVATE_DOMAINS
+                    .iter()
+                    .any(|private| matches_domain(&domain, private))
+        }
+    }
+}
+
+fn is_private_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // "this network" and the shared address space of carrier-grade NAT
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn is_private_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // unique local addresses
+        || first & 0xfe00 == 0xfc00
+        // link-local addresses
+        || first & 0xffc0 == 0xfe80
+        || ip.to_ipv4_mapped().as_ref().is_some_and(is_private_v4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(filter: &LinkFilter, url: &str) -> Result<(), Rejection> {
+        filter.check(&url.parse().unwrap())
+    }
+
+    #[test]
+    fn private_hosts() {
+        let filter = LinkFilter::new().reject_private(true);
+        for url in &[
+            "http://localhost:8080/",
+            "http://app.localhost/",
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://172.20.0.1/",
+            "http://192.168.0.10/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://2130706433/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:192.168.0.1]/",
+            "http://intranet/wiki",
+            "http://printer.local/",
+            "http://jira.corp.internal/",
+            "http://nas.home.arpa/",
+        ] {
+            assert_eq!(check(&filter, url), Err(Rejection::Private), "{}", url);
+        }
+        for url in &[
+            "https://example.com/",
+            "http://8.8.8.8/",
+            "http://172.32.0.1/",
+            "http://[2001:db8::1]/",
+            "https://localhost.example.com/",
+        ] {
+            assert_eq!(check(&filter, url), Ok(()), "{}", url);
+        }
+        assert_eq!(check(&LinkFilter::new(), "http://localhost/"), Ok(()));
+    }
+
+    #[test]
+    fn rules() {
+        let filter = LinkFilter::new()
+            .allow_domain("example.com")
+            .allow_domain("*.rs")
+            .deny_domain("intranet-*.example.com")
+            .exclude(Regex::new(r"/private/").unwrap())
+            .include(Regex::new(r"^https://").unwrap());
+        assert_eq!(check(&filter, "https://www.example.com/a"), Ok(()));
+        assert_eq!(check(&filter, "https://docs.rs/url"), Ok(()));
+        assert_eq!(
+            check(&filter, "https://example.org/"),
+            Err(Rejection::NotAllowedDomain)
+        );
+        assert_eq!(
+            check(&filter, "https://wiki.intranet-eu.example.com/"),
+            Err(Rejection::DeniedDomain(
+                "intranet-*.example.com".to_string()
+            ))
+        );
+        assert_eq!(
+            check(&filter, "https://example.com/private/x"),
+            Err(Rejection::Excluded("/private/".to_string()))
+        );
+        assert_eq!(
+            check(&filter, "http://example.com/"),
+            Err(Rejection::NotIncluded)
+        );
+
+        assert!(glob_match("10.1.2.3", "10.*"));
+        assert!(glob_match("a-b-c", "a*b*c"));
+        assert!(!glob_match("abc", "a*bc*d"));
+        assert!(!glob_match("ab", "a*b*b"));
+    }
+}
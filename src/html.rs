@@ -0,0 +1,280 @@
+//! A minimal scanner for the start tags and attributes of html documents.
+//!
+//! This is not a full html parser, it only finds start tags and reads their attributes, which
+//! is all that is needed to find the links and resources of a page.
+
+use std::ops::Range;
+
+/// A start tag like `<a href="...">` within an html document.
+#[derive(Debug, Clone)]
+pub(crate) struct Tag<'a> {
+    /// The name of the tag in lowercase
+    pub name: String,
+    /// The position of the whole tag within the document, including `<` and `>`
+    pub range: Range<usize>,
+    /// The source of the whole document
+    html: &'a str,
+}
+
+impl<'a> Tag<'a> {
+    /// The raw value of the attribute `name`, with its position in the document.
+    ///
+    /// The position excludes the quotes around the value.
+    pub fn attr_range(&self, name: &str) -> Option<(Range<usize>, &'a str)> {
+        let tag = &self.html[self.range.clone()];
+        // skip the tag name
+        let mut pos = 1 + self.name.len();
+        let bytes = tag.as_bytes();
+        while pos < bytes.len() {
+            while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+                pos += 1;
+            }
+            if pos >= bytes.len() || bytes[pos] == b'>' {
+                return None;
+            }
+            let key_start = pos;
+            while pos < bytes.len()
+                && !bytes[pos].is_ascii_whitespace()
+                && !matches!(bytes[pos], b'=' | b'>' | b'/')
+            {
+                pos += 1;
+            }
+            let key = &tag[key_start..pos];
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos >= bytes.len() || bytes[pos] != b'=' {
+                // an attribute without value
+                if key.eq_ignore_ascii_case(name) {
+                    return Some((self.range.start + pos..self.range.start + pos, ""));
+                }
+                continue;
+            }
+            pos += 1;
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let value = match bytes.get(pos) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let start = pos + 1;
+                    let end = tag[start..]
+                        .find(quote as char)
+                        .map(|i| start + i)
+                        .unwrap_or(tag.len());
+                    pos = end + 1;
+                    start..end
+                }
+                _ => {
+                    let start = pos;
+                    while pos < bytes.len()
+                        && !bytes[pos].is_ascii_whitespace()
+                        && bytes[pos] != b'>'
+                    {
+                        pos += 1;
+                    }
+                    start..pos
+                }
+            };
+            if key.eq_ignore_ascii_case(name) {
+                let range = self.range.start + value.start..self.range.start + value.end;
+                return Some((range, &tag[value]));
+            }
+        }
+        None
+    }
+
+    /// The value of the attribute `name` with html entities decoded.
+    pub fn attr(&self, name: &str) -> Option<String> {
+        self.attr_range(name)
+            .map(|(_, value)| decode_entities(value))
+    }
+
+    /// The text directly following the tag up to the next tag, with entities decoded and
+    /// surrounding whitespace removed.
+    ///
+    /// A `<![CDATA[...]]>` section is returned as it is.
+    pub fn inner_text(&self) -> String {
+        let rest = self.html[self.range.end..].trim_start();
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            return cdata[..end].trim().to_string();
+        }
+        let end = rest.find('<').unwrap_or(rest.len());
+        decode_entities(rest[..end].trim())
+    }
+
+    /// The name of the tag without a namespace prefix, like `loc` for `<sitemap:loc>`
+    pub fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    /// The position of the content of a raw text element like `<script>` or `<style>`, up to
+    /// its end tag.
+    pub fn raw_text(&self) -> Range<usize> {
+        let rest = &self.html[self.range.end..];
+        let close = format!("</{}", self.name);
+        let end = find_ignore_case(rest, &close).unwrap_or(rest.len());
+        self.range.end..self.range.end + end
+    }
+
+    /// The end of the matching end tag of a raw text element like `<script>` or `<style>`.
+    pub fn raw_text_end(&self) -> usize {
+        let close = self.raw_text().end;
+        self.html[close..]
+            .find('>')
+            .map(|i| close + i + 1)
+            .unwrap_or(self.html.len())
+    }
+}
+
+/// Returns all start tags of the document in order.
+///
+/// Comments are skipped and so is the content of `<script>` and `<style>` elements.
+pub(crate) fn tags(html: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = html[pos..].find('<').map(|i| pos + i) {
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = rest
+                .find("-->")
+                .map(|i| start + i + 3)
+                .unwrap_or(html.len());
+            continue;
+        }
+        let name_len = rest[1..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len() - 1);
+        let name = &rest[1..1 + name_len];
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            pos = start + 1;
+            continue;
+        }
+        let end = match tag_end(rest) {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = Tag {
+            name: name.to_ascii_lowercase(),
+            range: start..end,
+            html,
+        };
+        pos = if tag.name == "script" || tag.name == "style" {
+            tag.raw_text_end()
+        } else {
+            end
+        };
+        tags.push(tag);
+    }
+    tags
+}
+
+/// Finds the end of a tag that starts at the beginning of `rest`, skipping quoted values.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Finds `needle` in `haystack` ignoring ascii case.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Decodes the html entities that commonly appear in attribute values.
+pub(crate) fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                num if num.starts_with("#x") || num.starts_with("#X") => {
+                    u32::from_str_radix(&num[2..], 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                }
+                num if num.starts_with('#') => num[1..].parse().ok().and_then(std::char::from_u32),
+                _ => None,
+            };
+            c.map(|c| (c, end))
+        });
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_tags() {
+        let html = r#"<html><!-- <a href="/commented"> --><A HREF='/a?x=1&amp;y=2' class=x>
+<script>if (a < b) { document.write("<a href=/script>") }</script>
+<img src=/img.png alt="a > b"><link rel=stylesheet href="/style.css"/></html>"#;
+        let tags = tags(html);
+        let names: Vec<_> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["html", "a", "script", "img", "link"]);
+        assert_eq!(tags[1].attr("href").as_deref(), Some("/a?x=1&y=2"));
+        assert_eq!(tags[1].attr("class").as_deref(), Some("x"));
+        assert_eq!(tags[3].attr("src").as_deref(), Some("/img.png"));
+        assert_eq!(tags[3].attr("alt").as_deref(), Some("a > b"));
+        assert_eq!(tags[4].attr("href").as_deref(), Some("/style.css"));
+        assert_eq!(tags[4].attr("missing"), None);
+
+        let (range, _) = tags[3].attr_range("src").unwrap();
+        assert_eq!(&html[range], "/img.png");
+        assert!(html[tags[2].raw_text_end()..].starts_with("\n<img"));
+        assert!(html[tags[2].raw_text()].ends_with("/script>\") }"));
+    }
+
+    #[test]
+    fn xml_text() {
+        let xml =
+            "<s:loc> http://example.com/?a=1&amp;b=2 </s:loc><title><![CDATA[a <b>]]></title>";
+        let tags = tags(xml);
+        assert_eq!(tags[0].local_name(), "loc");
+        assert_eq!(tags[0].inner_text(), "http://example.com/?a=1&b=2");
+        assert_eq!(tags[1].inner_text(), "a <b>");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &#38; &#x26; &unknown;"),
+            "a & b & & &unknown;"
+        );
+    }
+}
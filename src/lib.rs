@@ -62,26 +62,181 @@
 //! # }
 //! ```
 //!
+//! ### Wayback Machine
+//! The `WaybackClient` saves urls to the Wayback Machine of the Internet Archive instead.
+//! Both clients implement the `Archiver` trait, so code can target either service.
+//!
+//! ```no_run
+//! # use archiveis::{Archiver, ArchiveClient, WaybackClient};
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let url = "http://example.com/".parse()?;
+//! let archivers: Vec<Box<dyn Archiver + Send + Sync>> = vec![
+//!     Box::new(ArchiveClient::default()),
+//!     Box::new(WaybackClient::default()),
+//! ];
+//! for archiver in &archivers {
+//!     let archived = archiver.capture(&url).await?;
+//!     println!("{}: {}", archived.provider, archived.archived_url);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ### Local snapshots
+//! With the `local` feature enabled, the `LocalArchiver` stores self-contained snapshots of pages
+//! with all their stylesheets, images and scripts inlined in a local directory, for when no
+//! archive service is reachable.
+//!
+//! ### Extracting links
+//! The `extract` module finds the links of html, Markdown and plain text documents, which can be
+//! restricted to certain domains.
+//!
+//! ### Normalizing urls
+//! The `normalize` module removes fragments and tracking parameters from urls, so that variants
+//! of the same page are captured once. `MultiArchiver::normalize` applies a `Normalizer` to every
+//! capture and collapses duplicates in `capture_all`.
+//!
+//! ### Filtering urls
+//! With the `filter` feature enabled, a `filter::LinkFilter` rejects urls by regular expressions,
+//! domains and private ip addresses. `MultiArchiver::filter` applies it to every capture and
+//! `MultiArchiver::plan` shows what would be captured.
+//!
+//! ### Bookmarks
+//! With the `bookmarks` feature enabled, the `bookmarks` module reads the bookmarks exported by
+//! browsers and link saving services together with their titles, folders and tags.
+//!
+//! ### Feeds
+//! The `feed` module reads the entries of RSS and Atom feeds, a `store::FeedStore` remembers
+//! which of them were already captured.
+//!
+//! ### Scanning directories
+//! With the `scan` feature enabled, the `scan` module finds the links in all files of a
+//! directory like a source tree, respecting `.gitignore`.
+//!
+//! ### Sitemaps
+//! With the `sitemap` feature enabled, the `sitemap` module reads the pages of a site, with their
+//! modification dates, from its sitemap or sitemap index.
+//!
+//! ### Templates
+//! The `template` module formats archived links as lines of a template like
+//! `[{target}]({archive})`, with the values escaped for Markdown, html or the shell.
+//!
+//! ### Capture history
+//! The `store` module records every capture in a `CaptureStore`, with the `sqlite` feature
+//! enabled `store::SqliteStore` keeps them in a SQLite database that can be queried by url and
+//! date.
+//!
+//! ### Async runtimes
+//! The async `ArchiveClient` runs on tokio via reqwest by default (`runtime-tokio` feature).
+//! To use it with async-std instead, disable the default features and enable `runtime-async-std`,
+//! which uses surf as HTTP transport.
+//!
+//! ### Blocking client
+//! With the `blocking` feature enabled, `archiveis::blocking::ArchiveClient` offers the same
+//! capturing methods without requiring an async runtime.
+//!
 
 //#![deny(warnings)]
+// builds without an http client or optional module don't log anything
+#[allow(unused_imports)]
 #[macro_use]
 extern crate log;
 
 #[cfg(feature = "with-serde")]
 use serde::{Deserialize, Serialize};
 
-use chrono::offset::TimeZone;
 use chrono::DateTime;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
 use futures::{stream, StreamExt};
-use reqwest::{header, IntoUrl};
 use std::fmt;
-use std::rc::Rc;
+use url::Url;
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use async_trait::async_trait;
+#[cfg(all(
+    feature = "filter",
+    any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    )
+))]
+use filter::LinkFilter;
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+use normalize::Normalizer;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use std::sync::Mutex;
+
+pub use archiver::{Archiver, Provider};
+#[cfg(all(
+    feature = "local",
+    any(feature = "runtime-tokio", feature = "runtime-async-std")
+))]
+pub use local::{LocalArchiver, SnapshotFormat};
+#[cfg(feature = "filter")]
+pub use multi::Plan;
+pub use multi::{MultiArchived, MultiArchiver, Policy};
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+pub use wayback::WaybackClient;
+
+mod archiver;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "bookmarks")]
+pub mod bookmarks;
+pub mod extract;
+pub mod feed;
+#[cfg(feature = "filter")]
+pub mod filter;
+mod html;
+#[cfg(all(
+    feature = "local",
+    any(feature = "runtime-tokio", feature = "runtime-async-std")
+))]
+mod local;
+mod multi;
+pub mod normalize;
+mod parse;
+pub mod rewrite;
+#[cfg(feature = "scan")]
+pub mod scan;
+#[cfg(feature = "sitemap")]
+pub mod sitemap;
+pub mod store;
+pub mod template;
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+mod transport;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+mod wayback;
 
 /// The Error Type used in this crate
 #[derive(Debug)]
 pub enum Error {
     /// Represents an error originated from hyper
+    #[cfg(any(feature = "runtime-tokio", feature = "blocking"))]
     Reqwest(reqwest::Error),
+    /// Represents an error originated from surf
+    #[cfg(feature = "runtime-async-std")]
+    Surf(surf::Error),
+    /// Means that the provided url could not be parsed
+    Url(url::ParseError),
+    /// Represents an error while reading or writing local files
+    Io(std::io::Error),
+    /// Represents an error originated from the SQLite capture store
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    /// Means that the capture store contains a malformed record
+    Store(String),
     /// Means that no token could be obtained from archive.is
     MissingToken,
     /// Means that the POST was successful but no archive url to the requested
@@ -89,23 +244,91 @@ pub enum Error {
     MissingUrl(String),
     /// An error occurred on the archiveis server while archiving an url
     ServerError(String),
+    /// Means that a document, like a sitemap, could not be parsed
+    InvalidDocument(String),
+    /// Means that the archive service refused to handle the request for the url, because too
+    /// many requests were sent or it asks to solve a CAPTCHA first
+    RateLimited(String),
+    /// Means that the url was not captured, because the filter of the `MultiArchiver` rejects it
+    Rejected(String),
 }
 
+impl Error {
+    /// A short machine readable name of the kind of error, like `http` or `missing-token`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            #[cfg(any(feature = "runtime-tokio", feature = "blocking"))]
+            Error::Reqwest(_) => "http",
+            #[cfg(feature = "runtime-async-std")]
+            Error::Surf(_) => "http",
+            Error::Url(_) => "invalid-url",
+            Error::Io(_) => "io",
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(_) => "store",
+            Error::Store(_) => "store",
+            Error::MissingToken => "missing-token",
+            Error::MissingUrl(_) => "missing-url",
+            Error::ServerError(_) => "server-error",
+            Error::InvalidDocument(_) => "invalid-document",
+            Error::RateLimited(_) => "rate-limited",
+            Error::Rejected(_) => "rejected",
+        }
+    }
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "blocking"))]
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
         Error::Reqwest(err)
     }
 }
 
+#[cfg(feature = "runtime-async-std")]
+impl From<surf::Error> for Error {
+    fn from(err: surf::Error) -> Self {
+        Error::Surf(err)
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(err: url::ParseError) -> Self {
+        Error::Url(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sqlite(err)
+    }
+}
+
 impl std::error::Error for Error {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::MissingToken => write!(f, "Missing required token."),
+            #[cfg(any(feature = "runtime-tokio", feature = "blocking"))]
             Error::Reqwest(err) => err.fmt(f),
+            #[cfg(feature = "runtime-async-std")]
+            Error::Surf(err) => err.fmt(f),
+            Error::Url(err) => err.fmt(f),
+            Error::Io(err) => err.fmt(f),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(err) => err.fmt(f),
+            Error::Store(msg) => write!(f, "Invalid capture record: {}", msg),
             Error::MissingUrl(url) => write!(f, "Missing archiveis url after archiving {}", url),
             Error::ServerError(url) => write!(f, "Encountered server error for {}", url),
+            Error::InvalidDocument(msg) => write!(f, "Invalid document: {}", msg),
+            Error::RateLimited(url) => write!(f, "Rate limited while requesting {}", url),
+            Error::Rejected(msg) => write!(f, "Rejected url: {}", msg),
         }
     }
 }
@@ -113,6 +336,42 @@ impl fmt::Display for Error {
 /// Result type for this crate
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// A trait to try to convert some type into a `Url`.
+pub trait IntoUrl {
+    /// Parses `self` as `Url`
+    fn into_url(self) -> Result<Url>;
+}
+
+impl IntoUrl for Url {
+    fn into_url(self) -> Result<Url> {
+        Ok(self)
+    }
+}
+
+impl IntoUrl for &Url {
+    fn into_url(self) -> Result<Url> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoUrl for &str {
+    fn into_url(self) -> Result<Url> {
+        Ok(Url::parse(self)?)
+    }
+}
+
+impl IntoUrl for &String {
+    fn into_url(self) -> Result<Url> {
+        self.as_str().into_url()
+    }
+}
+
+impl IntoUrl for String {
+    fn into_url(self) -> Result<Url> {
+        self.as_str().into_url()
+    }
+}
+
 /// Represents a result of the capture service
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
@@ -123,55 +382,89 @@ pub struct Archived {
     pub archived_url: String,
     /// The time stamp when the site was archived
     pub time_stamp: Option<DateTime<chrono::Utc>>,
-    /// The submitid token used to authorize access on the archive.is server
+    /// The submitid token used to authorize access on the archive.is server,
+    /// empty for services that don't use tokens
     pub submit_token: String,
+    /// The service that stores the snapshot
+    #[cfg_attr(feature = "with-serde", serde(default))]
+    pub provider: Provider,
 }
 
+/// The archive.is page that hands out the `submitid` tokens
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+const ARCHIVE_URL: &str = "http://archive.is/";
+
+/// The archive.is endpoint that accepts capture requests
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+const SUBMIT_URL: &str = "http://archive.is/submit/";
+
 /// A Client that serves as a wrapper around the archive.is capture service
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
 pub struct ArchiveClient {
-    /// The internal Hyper Http Client.
-    client: Rc<reqwest::Client>,
+    /// The internal Http Client of the selected runtime.
+    client: transport::HttpClient,
+    /// The submit token reused by the `Archiver` implementation.
+    token: Mutex<Option<String>>,
+    /// How the requested urls are normalized and filtered.
+    targets: Targets,
 }
 
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
 impl ArchiveClient {
     /// Creates a new instance of the `ArchiveClient` using a special user agent
     pub fn new<T: ToString>(user_agent: T) -> Self {
-        let mut headers = header::HeaderMap::with_capacity(1);
-        headers.insert(
-            header::USER_AGENT,
-            user_agent
-                .to_string()
-                .parse()
-                .expect("Failed to parse user agent."),
-        );
-        let client = reqwest::ClientBuilder::default()
-            .default_headers(headers)
-            .build()
-            .expect("Failed to create reqwest client");
-
         ArchiveClient {
-            client: Rc::new(client),
+            client: transport::HttpClient::new(&user_agent.to_string()),
+            token: Mutex::new(None),
+            targets: Targets::default(),
         }
     }
 
+    /// Normalizes every url with `normalizer` before it is captured or looked up,
+    /// `capture_all` captures urls that are the same after normalizing only once
+    pub fn normalize(mut self, normalizer: Normalizer) -> Self {
+        self.targets.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Captures only the urls accepted by `filter`, which checks the normalized urls.
+    ///
+    /// Capturing a rejected url fails with `Error::Rejected` without sending a request.
+    #[cfg(feature = "filter")]
+    pub fn filter(mut self, filter: LinkFilter) -> Self {
+        self.targets.filter = Some(filter);
+        self
+    }
+
     /// Invokes the archive.is capture service on each url provided.
     ///
     /// If no token was passed, a fresh token is obtained via `get_unique_token`,
     /// afterwards all capture requests are joined in a single future that returns
     /// a `Vec<Result<Archived, Error>>` which holds every result of the individual
     /// capturing requests, so every single capture request gets executed regardless
-    /// of the success of prior requests.
+    /// of the success of prior requests. With a normalizer, urls that are the same after
+    /// normalizing are captured once, so there may be fewer results than `links`.
     pub async fn capture_all<U: IntoUrl>(self, links: Vec<U>) -> Result<Vec<Result<Archived>>> {
         let token = self.get_unique_token().await?;
 
-        Ok(stream::iter(
-            links
-                .into_iter()
-                .map(|url| async { self.capture_with_token(url, token.clone()).await }),
+        Ok(
+            stream::iter(self.targets.dedup(links).into_iter().map(|url| {
+                let token = token.clone();
+                let client = &self;
+                async move { client.capture_with_token(url?, token).await }
+            }))
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await,
         )
-        .buffer_unordered(10)
-        .collect::<Vec<_>>()
-        .await)
     }
 
     /// Invokes the archive.is capture service.
@@ -202,79 +495,15 @@ impl ArchiveClient {
         url: U,
         submit_token: T,
     ) -> Result<Archived> {
-        let target_url = url.into_url()?;
+        let target_url = self.targets.check(url.into_url()?)?;
         let submit_token = submit_token.to_string();
-        let body: String = url::form_urlencoded::Serializer::new(String::new())
-            .append_pair("url", target_url.as_str())
-            .append_pair("anyway", "1")
-            .append_pair("submitid", &submit_token)
-            .finish();
 
         let resp = self
             .client
-            .post(target_url.clone())
-            .body(body)
-            .send()
+            .post_form(SUBMIT_URL, submit_body(&target_url, &submit_token))
             .await?;
 
-        if let Some(archived_url) = resp.headers().get("Refresh").and_then(|x| {
-            x.to_str()
-                .ok()
-                .and_then(|x| x.split('=').nth(1).map(str::to_string))
-        }) {
-            // parse the timemap from the Date header
-            let time_stamp = resp.headers().get("Date").and_then(|x| {
-                x.to_str()
-                    .ok()
-                    .and_then(|x| chrono::Utc.datetime_from_str(x, "%a, %e %b %Y %T GMT").ok())
-            });
-            let archived = Archived {
-                target_url: target_url.to_string(),
-                archived_url,
-                time_stamp,
-                submit_token: submit_token.to_string(),
-            };
-            debug!(
-                "Archived target url {} at {}",
-                archived.target_url, archived.archived_url
-            );
-
-            return Ok(archived);
-        } else {
-            // an err response body can be empty, contain Server Error or
-            // can directly contain the archived site, in that case we extract the archived_url
-
-            if let Ok(html) = resp.text().await {
-                if html.starts_with("<h1>Server Error</h1>") {
-                    error!("Server Error while archiving {}", target_url);
-
-                    return Err(Error::ServerError(target_url.to_string()));
-                }
-                let archived_url =
-                    html.splitn(2, "<meta property=\"og:url\"")
-                        .nth(1)
-                        .and_then(|x| {
-                            x.splitn(2, "content=\"")
-                                .nth(1)
-                                .and_then(|id| id.splitn(2, '\"').next().map(str::to_owned))
-                        });
-                if let Some(archived_url) = archived_url {
-                    let archived = Archived {
-                        target_url: target_url.to_string(),
-                        archived_url,
-                        time_stamp: None,
-                        submit_token: submit_token.to_string(),
-                    };
-                    debug!(
-                        "Archived target url {} at {}",
-                        archived.target_url, archived.archived_url
-                    );
-                    return Ok(archived);
-                }
-            }
-            error!("Failed to archive {}", target_url);
-            return Err(Error::MissingUrl(target_url.into_string()));
-        }
+        archived_from_response(target_url, submit_token, &resp)
     }
 
     /// In order to submit an authorized capture request we need to first obtain a temporarily valid
@@ -283,35 +512,289 @@ impl ArchiveClient {
     /// This is achieved by sending a GET request to the archive.is domain and parsing the `
     /// `submitid` from the responding html.
     pub async fn get_unique_token(&self) -> Result<String> {
-        let html = self
-            .client
-            .get("http://archive.is/")
-            .send()
-            .await?
-            .text()
-            .await
-            .map_err(|_| Error::MissingToken)?;
+        let resp = self.client.get(ARCHIVE_URL).await?;
 
-        html.rsplitn(2, "name=\"submitid")
-            .next()
-            .and_then(|x| {
-                x.splitn(2, "value=\"")
-                    .nth(1)
-                    .and_then(|token| token.splitn(2, '\"').next().map(str::to_string))
-            })
-            .ok_or(Error::MissingToken)
+        token_from_response(&resp)
+    }
+
+    /// Looks up the most recent snapshot of `url` on archive.is.
+    ///
+    /// This sends a GET request to the archive.is timemap of the url, `None` is returned if
+    /// archive.is has no snapshot of the url yet. A `429` status is returned as
+    /// `Error::RateLimited`, any other error status as `Error::ServerError`.
+    pub async fn lookup<U: IntoUrl>(&self, url: U) -> Result<Option<Archived>> {
+        let target_url = self.targets.normalize(url.into_url()?);
+        let resp = self.client.get(&timemap_url(&target_url)).await?;
+        archived_from_timemap(target_url, Provider::ArchiveIs, &resp)
+    }
+
+    /// Returns the cached submit token, or obtains a new one.
+    async fn cached_token(&self) -> Result<String> {
+        let cached = self.token.lock().unwrap().clone();
+        if let Some(token) = cached {
+            return Ok(token);
+        }
+        let token = self.get_unique_token().await?;
+        *self.token.lock().unwrap() = Some(token.clone());
+        Ok(token)
     }
 }
 
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
 impl Default for ArchiveClient {
     fn default() -> Self {
         ArchiveClient::new("archiveis-rs")
     }
 }
 
+/// Since submit tokens stay valid for several minutes, the `Archiver` implementation obtains a
+/// token once and reuses it for every capture. The token is discarded after a `ServerError`, so
+/// that the next capture starts with a fresh one.
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+#[async_trait]
+impl Archiver for ArchiveClient {
+    fn provider(&self) -> Provider {
+        Provider::ArchiveIs
+    }
+
+    async fn capture(&self, url: &Url) -> Result<Archived> {
+        let token = self.cached_token().await?;
+        let archived = self.capture_with_token(url, token).await;
+        if let Err(Error::ServerError(_)) = archived {
+            self.token.lock().unwrap().take();
+        }
+        archived
+    }
+
+    async fn lookup(&self, url: &Url) -> Result<Option<Archived>> {
+        ArchiveClient::lookup(self, url).await
+    }
+}
+
+/// The normalizer and filter the archive.is clients apply to the requested urls
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Targets {
+    pub(crate) normalizer: Option<Normalizer>,
+    #[cfg(feature = "filter")]
+    pub(crate) filter: Option<LinkFilter>,
+}
+
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+impl Targets {
+    /// The url that is requested for `url`
+    pub(crate) fn normalize(&self, url: Url) -> Url {
+        match &self.normalizer {
+            Some(normalizer) => normalizer.normalize(&url),
+            None => url,
+        }
+    }
+
+    /// The normalized `url`, or `Error::Rejected` if the filter rejects it
+    pub(crate) fn check(&self, url: Url) -> Result<Url> {
+        let url = self.normalize(url);
+        #[cfg(feature = "filter")]
+        {
+            if let Some(filter) = &self.filter {
+                if let Err(rejection) = filter.check(&url) {
+                    return Err(Error::Rejected(format!("{} {}", url, rejection)));
+                }
+            }
+        }
+        Ok(url)
+    }
+
+    /// The normalized `links` without duplicates, links that aren't valid urls are kept as errors
+    pub(crate) fn dedup<U: IntoUrl>(&self, links: Vec<U>) -> Vec<Result<Url>> {
+        let urls = links.into_iter().map(IntoUrl::into_url);
+        match &self.normalizer {
+            Some(normalizer) => {
+                let mut invalid = Vec::new();
+                let valid: Vec<_> = urls
+                    .filter_map(|url| url.map_err(|err| invalid.push(Err(err))).ok())
+                    .collect();
+                let mut urls: Vec<_> = normalizer
+                    .dedup(valid)
+                    .into_iter()
+                    .map(|normalized| Ok(normalized.url))
+                    .collect();
+                urls.extend(invalid);
+                urls
+            }
+            None => urls.collect(),
+        }
+    }
+}
+
+/// The archive.is timemap that lists all snapshots of `url`
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn timemap_url(url: &Url) -> String {
+    format!("{}timemap/{}", ARCHIVE_URL, url)
+}
+
+/// Picks the most recent memento of a timemap.
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn latest_memento(
+    target_url: &Url,
+    provider: Provider,
+    timemap: &str,
+) -> Option<Archived> {
+    parse::parse_timemap(timemap)
+        .into_iter()
+        .max_by_key(|memento| memento.datetime)
+        .map(|memento| Archived {
+            target_url: target_url.to_string(),
+            archived_url: memento.url,
+            time_stamp: memento.datetime,
+            submit_token: String::new(),
+            provider,
+        })
+}
+
+/// Reads the most recent snapshot from a timemap response.
+///
+/// A `404` means that there is no snapshot of the url yet, any other error status is reported
+/// instead of being read as an empty timemap.
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn archived_from_timemap(
+    target_url: Url,
+    provider: Provider,
+    resp: &transport::Response,
+) -> Result<Option<Archived>> {
+    match resp.status {
+        404 => Ok(None),
+        429 => {
+            error!("Rate limited while looking up {}", target_url);
+            Err(Error::RateLimited(target_url.into()))
+        }
+        _ if !resp.is_success() => {
+            error!(
+                "Looking up {} failed with status {}",
+                target_url, resp.status
+            );
+            Err(Error::ServerError(target_url.into()))
+        }
+        _ => Ok(latest_memento(&target_url, provider, &resp.text())),
+    }
+}
+
+/// Reads the submit token from the archive.is front page.
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn token_from_response(resp: &transport::Response) -> Result<String> {
+    let html = resp.text();
+    // only the front page has the submit form, a CAPTCHA page doesn't
+    if resp.status != 429 && html.contains("name=\"submitid") {
+        if let Some(token) = parse::parse_submit_token(&html) {
+            return Ok(token);
+        }
+    }
+    if resp.is_rate_limited() {
+        error!("Rate limited while requesting a submit token");
+        return Err(Error::RateLimited(ARCHIVE_URL.to_string()));
+    }
+    Err(Error::MissingToken)
+}
+
+/// The `x-www-form-urlencoded` body of a capture request
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn submit_body(target_url: &Url, submit_token: &str) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("url", target_url.as_str())
+        .append_pair("anyway", "1")
+        .append_pair("submitid", submit_token)
+        .finish()
+}
+
+/// Reads the archive url from a capture response.
+///
+/// The link to the archived page is usually contained in the `Refresh` header, in that case the
+/// time stamp is taken from the `Date` header. Otherwise the body can be empty, contain a Server
+/// Error or directly contain the archived site, in that case we extract the archived url from its
+/// meta information. Only a response without archive url is checked for rate limiting, because
+/// the archived site itself may embed a CAPTCHA.
+#[cfg(any(
+    feature = "runtime-tokio",
+    feature = "runtime-async-std",
+    feature = "blocking"
+))]
+pub(crate) fn archived_from_response(
+    target_url: Url,
+    submit_token: String,
+    resp: &transport::Response,
+) -> Result<Archived> {
+    let refresh = resp
+        .header("Refresh")
+        .and_then(parse::parse_refresh)
+        .and_then(|refresh| refresh.url);
+    let html = resp.text();
+    let (archived_url, time_stamp) = if let Some(archived_url) = refresh {
+        let time_stamp = resp.header("Date").and_then(parse::parse_http_date);
+        (archived_url, time_stamp)
+    } else if let Some(archived_url) = parse::parse_og_url(&html) {
+        (archived_url, None)
+    } else if html.starts_with("<h1>Server Error</h1>") {
+        error!("Server Error while archiving {}", target_url);
+        return Err(Error::ServerError(target_url.into()));
+    } else if resp.is_rate_limited() {
+        error!("Rate limited while archiving {}", target_url);
+        return Err(Error::RateLimited(target_url.into()));
+    } else {
+        error!("Failed to archive {}", target_url);
+        return Err(Error::MissingUrl(target_url.into()));
+    };
+    let archived = Archived {
+        target_url: target_url.into(),
+        archived_url,
+        time_stamp,
+        submit_token,
+        provider: Provider::ArchiveIs,
+    };
+    debug!(
+        "Archived target url {} at {}",
+        archived.target_url, archived.archived_url
+    );
+    Ok(archived)
+}
+
 #[cfg(test)]
 mod tests {
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    use super::*;
+
     #[test]
+    #[allow(clippy::needless_splitn)]
     fn extract_unique_token() {
         let html = r###"type="hidden" name="submitid" value="1yPA39C6QcM84Dzspl+7s28rrAFOnliPMCiJtoP+OlTKmd5kJd21G4ucgTkx0mnZ"/>"###;
 
@@ -329,4 +812,211 @@ mod tests {
             split
         );
     }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    fn response(headers: &[(&str, &str)], body: &str) -> transport::Response {
+        transport::Response {
+            status: 200,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn archived_from_refresh() {
+        let target: Url = "http://example.com/?a=b".parse().unwrap();
+        let resp = response(
+            &[
+                ("refresh", "0;url=http://archive.is/wip/abcd"),
+                ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ],
+            "",
+        );
+        let archived = archived_from_response(target, "token".to_string(), &resp).unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/wip/abcd");
+        assert!(archived.time_stamp.is_some());
+    }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn archived_from_body() {
+        let target: Url = "http://example.com/".parse().unwrap();
+        let html = r#"<html><head><meta property="og:url" content="http://archive.is/abcd"/>"#;
+        let archived =
+            archived_from_response(target.clone(), "token".to_string(), &response(&[], html))
+                .unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/abcd");
+
+        let resp = response(&[], "<h1>Server Error</h1>");
+        match archived_from_response(target.clone(), "token".to_string(), &resp) {
+            Err(Error::ServerError(url)) => assert_eq!(url, "http://example.com/"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let resp = response(
+            &[],
+            r#"<form><div class="g-recaptcha" data-sitekey="x"></div>"#,
+        );
+        match archived_from_response(target.clone(), "token".to_string(), &resp) {
+            Err(Error::RateLimited(url)) => assert_eq!(url, "http://example.com/"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn archived_page_with_captcha() {
+        // an archived page can contain a CAPTCHA of the archived site itself
+        let target: Url = "http://example.com/".parse().unwrap();
+        let html = r#"<html><head><meta property="og:url" content="http://archive.is/abcd"/>
+<body><form><div class="g-recaptcha" data-sitekey="x"></div></form>"#;
+        let archived =
+            archived_from_response(target.clone(), "token".to_string(), &response(&[], html))
+                .unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/abcd");
+
+        let resp = response(&[("refresh", "0;url=http://archive.is/wip/abcd")], html);
+        let archived = archived_from_response(target, "token".to_string(), &resp).unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/wip/abcd");
+
+        let front =
+            r#"<input type="hidden" name="submitid" value="abcd"/><div class="g-recaptcha">"#;
+        assert_eq!(token_from_response(&response(&[], front)).unwrap(), "abcd");
+        match token_from_response(&response(&[], r#"<div class="g-recaptcha">"#)) {
+            Err(Error::RateLimited(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn latest_memento_of_timemap() {
+        let target: Url = "http://example.com/".parse().unwrap();
+        let timemap = r#"<http://example.com/>; rel="original",
+<http://archive.is/abcd>; rel="first memento"; datetime="Wed, 02 Jan 2013 00:00:00 GMT",
+<http://archive.is/efgh>; rel="last memento"; datetime="Thu, 03 Jan 2013 00:00:00 GMT""#;
+        let archived = latest_memento(&target, Provider::ArchiveIs, timemap).unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/efgh");
+        assert_eq!(archived.target_url, "http://example.com/");
+        assert!(latest_memento(&target, Provider::ArchiveIs, "").is_none());
+    }
+
+    #[cfg(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn archived_from_timemap_status() {
+        let target: Url = "http://example.com/".parse().unwrap();
+        let timemap =
+            r#"<http://archive.is/abcd>; rel="memento"; datetime="Wed, 02 Jan 2013 00:00:00 GMT""#;
+        let archived =
+            archived_from_timemap(target.clone(), Provider::ArchiveIs, &response(&[], timemap))
+                .unwrap()
+                .unwrap();
+        assert_eq!(archived.archived_url, "http://archive.is/abcd");
+
+        let mut resp = response(&[], "");
+        resp.status = 404;
+        assert!(
+            archived_from_timemap(target.clone(), Provider::ArchiveIs, &resp)
+                .unwrap()
+                .is_none()
+        );
+        resp.status = 429;
+        match archived_from_timemap(target.clone(), Provider::ArchiveIs, &resp) {
+            Err(Error::RateLimited(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        resp.status = 503;
+        match archived_from_timemap(target, Provider::ArchiveIs, &resp) {
+            Err(Error::ServerError(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+    #[test]
+    fn dedup_targets() {
+        let links = vec![
+            "http://example.com/?utm_source=feed",
+            "not a url",
+            "http://example.com/#top",
+            "http://example.com/other",
+        ];
+        let client = ArchiveClient::default();
+        assert_eq!(client.targets.dedup(links.clone()).len(), 4);
+
+        let client = client.normalize(Normalizer::default());
+        let urls = client.targets.dedup(links);
+        assert_eq!(urls.len(), 3);
+        assert_eq!(urls[0].as_ref().unwrap().as_str(), "http://example.com/");
+        assert_eq!(
+            urls[1].as_ref().unwrap().as_str(),
+            "http://example.com/other"
+        );
+        match &urls[2] {
+            Err(Error::Url(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    #[cfg_attr(
+        all(feature = "runtime-async-std", not(feature = "runtime-tokio")),
+        async_std::test
+    )]
+    async fn capture_rejects_invalid_url() {
+        let client = ArchiveClient::default();
+        match client.capture_with_token("not a url", "token").await {
+            Err(Error::Url(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[cfg(all(
+        feature = "filter",
+        any(feature = "runtime-tokio", feature = "runtime-async-std")
+    ))]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    #[cfg_attr(
+        all(feature = "runtime-async-std", not(feature = "runtime-tokio")),
+        async_std::test
+    )]
+    async fn capture_rejects_filtered_url() {
+        let client = ArchiveClient::default().filter(LinkFilter::new().reject_private(true));
+        match client
+            .capture_with_token("http://192.168.1.1/admin", "token")
+            .await
+        {
+            Err(Error::Rejected(msg)) => assert!(msg.starts_with("http://192.168.1.1/admin")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
 }
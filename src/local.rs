@@ -0,0 +1,796 @@
+//! An offline archive that stores self-contained snapshots on disk.
+//!
+//! When archive.is is blocked or down, the `LocalArchiver` still keeps a copy: it downloads the
+//! page together with its stylesheets, images and scripts and stores everything in a single file.
+//! It is only available with the `local` feature enabled.
+
+use crate::{html, transport, Archived, Archiver, Error, IntoUrl, Provider, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{NaiveDateTime, Utc};
+use encoding_rs::{Encoding, UTF_8};
+use futures::future;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{ErrorKind, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// The file format of the snapshots of a `LocalArchiver`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// A single html file with all resources inlined as `data:` urls
+    #[default]
+    Html,
+    /// A MIME html archive (`multipart/related`) as saved by browsers
+    Mhtml,
+}
+
+impl SnapshotFormat {
+    /// The file extension of the format
+    fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Html => "html",
+            SnapshotFormat::Mhtml => "mhtml",
+        }
+    }
+}
+
+/// A downloaded resource of a page
+#[derive(Debug, Clone)]
+struct Resource {
+    /// The mime type of the resource
+    mime: String,
+    /// The content of the resource
+    body: Vec<u8>,
+}
+
+impl Resource {
+    /// The resource as `data:` url
+    fn data_url(&self) -> String {
+        // parameters like the charset would need escaping in the url
+        let mime = self.mime.split(';').next().unwrap_or_default().trim();
+        format!("data:{};base64,{}", mime, STANDARD.encode(&self.body))
+    }
+
+    /// The content of a stylesheet or script, decoded with the charset of its mime type
+    fn text(&self) -> String {
+        let encoding = charset(&self.mime).unwrap_or(UTF_8);
+        encoding.decode(&self.body).0.into_owned()
+    }
+}
+
+/// The format of the time stamp in snapshot file names, with milliseconds
+const TIME_STAMP_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+/// The maximum length of the readable part of a snapshot file name
+const SLUG_LENGTH: usize = 100;
+
+/// An `Archiver` that stores self-contained snapshots of pages in a local directory
+///
+/// ```no_run
+/// # use archiveis::{LocalArchiver, SnapshotFormat};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let archiver = LocalArchiver::new("snapshots").format(SnapshotFormat::Html);
+/// let archived = archiver.capture("http://example.com/").await?;
+/// println!("snapshot stored at: {}", archived.archived_url);
+/// # Ok(())
+/// # }
+/// ```
+pub struct LocalArchiver {
+    /// The internal Http Client of the selected runtime.
+    client: transport::HttpClient,
+    /// Where the snapshots are stored
+    dir: PathBuf,
+    /// The file format of the snapshots
+    format: SnapshotFormat,
+}
+
+impl LocalArchiver {
+    /// Creates a new `LocalArchiver` that stores html snapshots in `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        LocalArchiver {
+            client: transport::HttpClient::new("archiveis-rs"),
+            dir: dir.into(),
+            format: SnapshotFormat::default(),
+        }
+    }
+
+    /// Sets the file format of the snapshots
+    pub fn format(mut self, format: SnapshotFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the user agent the page and its resources are requested with
+    pub fn user_agent<T: ToString>(mut self, user_agent: T) -> Self {
+        self.client = transport::HttpClient::new(&user_agent.to_string());
+        self
+    }
+
+    /// The directory the snapshots are stored in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Downloads the page at `url` together with its stylesheets, images and scripts and stores
+    /// them in a single file in the snapshot directory.
+    ///
+    /// Besides `<link>`ed stylesheets, icons, scripts and the `src` and `srcset` of images, the
+    /// `url()` references of stylesheets, `<style>` elements and `style` attributes are stored.
+    /// Resources that are only loaded by scripts, `@import` rules without `url()` and embedded
+    /// media like `<video>`, `<audio>` or `<iframe>` are not. Resources that can't be downloaded
+    /// keep their original url.
+    ///
+    /// The page is decoded with the charset of its `Content-Type` header or its `<meta>` tags
+    /// and stored as UTF-8. Every capture gets a new file, named after the url and the time of
+    /// the capture. The returned `Archived` points to the snapshot file with a `file://` url.
+    pub async fn capture<U: IntoUrl>(&self, url: U) -> Result<Archived> {
+        let target_url = url.into_url()?;
+        let resp = self.client.get(target_url.as_str()).await?;
+        if !resp.is_success() {
+            error!("Failed to download {}: status {}", target_url, resp.status);
+            return Err(Error::ServerError(target_url.into()));
+        }
+        let page = declare_utf8(&decode_page(&resp.body, resp.header("Content-Type")));
+
+        let mut resources = self.fetch_all(page_resources(&page, &target_url)).await;
+        // stylesheets can reference fonts and images themselves
+        let mut nested: Vec<_> = resources
+            .iter()
+            .filter(|(_, res)| res.mime.starts_with("text/css"))
+            .flat_map(|(url, res)| css_resources(&res.text(), url))
+            .filter(|url| !resources.contains_key(url))
+            .collect();
+        nested.sort();
+        nested.dedup();
+        resources.extend(self.fetch_all(nested).await);
+
+        let now = Utc::now();
+        let content = match self.format {
+            SnapshotFormat::Html => inline_page(&page, &target_url, &resources).into_bytes(),
+            SnapshotFormat::Mhtml => mhtml(&page, &target_url, &resources, now).into_bytes(),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{}-{}", slug(&target_url), now.format(TIME_STAMP_FORMAT));
+        let mut path = self
+            .dir
+            .join(format!("{}.{}", name, self.format.extension()));
+        // never overwrite a snapshot taken in the same millisecond
+        let mut counter = 1;
+        let mut file = loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    counter += 1;
+                    path =
+                        self.dir
+                            .join(format!("{}-{}.{}", name, counter, self.format.extension()));
+                }
+                file => break file?,
+            }
+        };
+        file.write_all(&content)?;
+
+        let archived = Archived {
+            target_url: target_url.into(),
+            archived_url: file_url(&path)?,
+            time_stamp: Some(now),
+            submit_token: String::new(),
+            provider: Provider::Local,
+        };
+        debug!(
+            "Archived target url {} at {}",
+            archived.target_url, archived.archived_url
+        );
+        Ok(archived)
+    }
+
+    /// Looks up the most recent snapshot of `url` in the snapshot directory.
+    pub async fn lookup<U: IntoUrl>(&self, url: U) -> Result<Option<Archived>> {
+        let target_url = url.into_url()?;
+        let prefix = format!("{}-", slug(&target_url));
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut latest: Option<((NaiveDateTime, u32), PathBuf)> = None;
+        for entry in entries {
+            let path = entry?.path();
+            let time_stamp = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_prefix(&prefix))
+                .and_then(snapshot_time);
+            if let Some(time_stamp) = time_stamp {
+                let newer = match &latest {
+                    Some((latest, _)) => time_stamp > *latest,
+                    None => true,
+                };
+                if newer {
+                    latest = Some((time_stamp, path));
+                }
+            }
+        }
+        latest
+            .map(|((time_stamp, _), path)| {
+                Ok(Archived {
+                    target_url: target_url.into(),
+                    archived_url: file_url(&path)?,
+                    time_stamp: Some(time_stamp.and_utc()),
+                    submit_token: String::new(),
+                    provider: Provider::Local,
+                })
+            })
+            .transpose()
+    }
+
+    /// Downloads all resources concurrently, skipping the ones that fail.
+    async fn fetch_all(&self, urls: Vec<Url>) -> HashMap<Url, Resource> {
+        future::join_all(urls.into_iter().map(|url| async move {
+            match self.client.get(url.as_str()).await {
+                Ok(resp) if resp.is_success() => {
+                    let mime = resp
+                        .header("Content-Type")
+                        .map(str::to_string)
+                        .unwrap_or_else(|| guess_mime(&url).to_string());
+                    Some((
+                        url,
+                        Resource {
+                            mime,
+                            body: resp.body,
+                        },
+                    ))
+                }
+                _ => {
+                    warn!("Failed to download resource {}", url);
+                    None
+                }
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[async_trait]
+impl Archiver for LocalArchiver {
+    fn provider(&self) -> Provider {
+        Provider::Local
+    }
+
+    async fn capture(&self, url: &Url) -> Result<Archived> {
+        LocalArchiver::capture(self, url).await
+    }
+
+    async fn lookup(&self, url: &Url) -> Result<Option<Archived>> {
+        LocalArchiver::lookup(self, url).await
+    }
+}
+
+/// The url of the stylesheet, image or script a tag references, if any.
+fn resource_of(tag: &html::Tag) -> Option<(std::ops::Range<usize>, String)> {
+    let attr = match tag.name.as_str() {
+        "img" | "script" => "src",
+        "link" => {
+            let rel = tag.attr("rel").unwrap_or_default().to_lowercase();
+            if !rel
+                .split_whitespace()
+                .any(|rel| rel == "stylesheet" || rel == "icon")
+            {
+                return None;
+            }
+            "href"
+        }
+        _ => return None,
+    };
+    let (range, _) = tag.attr_range(attr)?;
+    let value = tag.attr(attr)?;
+    if value.is_empty() || value.starts_with("data:") {
+        return None;
+    }
+    Some((range, value))
+}
+
+/// The urls of the image candidates of a `srcset` attribute, like `a.png 1x, b.png 2x`.
+fn srcset_urls(srcset: &str) -> Vec<(Range<usize>, &str)> {
+    let bytes = srcset.as_bytes();
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b',') {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        // trailing commas end a candidate without descriptor
+        let end = start + srcset[start..pos].trim_end_matches(',').len();
+        if end > start && !srcset[start..end].starts_with("data:") {
+            urls.push((start..end, &srcset[start..end]));
+        }
+        if end == pos {
+            // skip the width or density descriptor
+            while pos < bytes.len() && bytes[pos] != b',' {
+                pos += 1;
+            }
+        }
+    }
+    urls
+}
+
+/// All resources of the page that should be stored with it.
+fn page_resources(page: &str, base: &Url) -> Vec<Url> {
+    let mut urls = Vec::new();
+    for tag in html::tags(page) {
+        if tag.name == "style" {
+            urls.extend(css_resources(&page[tag.raw_text()], base));
+            continue;
+        }
+        if let Some((_, url)) = resource_of(&tag) {
+            urls.extend(base.join(&url).ok());
+        }
+        if let Some((_, srcset)) = tag.attr_range("srcset") {
+            urls.extend(
+                srcset_urls(srcset)
+                    .into_iter()
+                    .filter_map(|(_, url)| base.join(&html::decode_entities(url)).ok()),
+            );
+        }
+        if let Some(style) = tag.attr("style") {
+            urls.extend(css_resources(&style, base));
+        }
+    }
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// Finds all `url(...)` references of a stylesheet.
+fn css_urls(css: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut urls = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = css[pos..].find("url(").map(|i| pos + i + 4) {
+        let end = match css[start..].find(')') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let raw = &css[start..end];
+        let trimmed = raw.trim().trim_matches(|c| c == '"' || c == '\'');
+        if !trimmed.is_empty() && !trimmed.starts_with("data:") {
+            urls.push((start..end, trimmed));
+        }
+        pos = end;
+    }
+    urls
+}
+
+/// All resources referenced by a stylesheet.
+fn css_resources(css: &str, base: &Url) -> Vec<Url> {
+    css_urls(css)
+        .into_iter()
+        .filter_map(|(_, url)| base.join(url).ok())
+        .collect()
+}
+
+/// Replaces every `url(...)` of a stylesheet with the downloaded resource.
+fn inline_css(css: &str, base: &Url, resources: &HashMap<Url, Resource>) -> String {
+    let mut inlined = css.to_string();
+    for (range, url) in css_urls(css).into_iter().rev() {
+        if let Some(res) = base.join(url).ok().and_then(|url| resources.get(&url)) {
+            inlined.replace_range(range, &format!("\"{}\"", res.data_url()));
+        }
+    }
+    inlined
+}
+
+/// Inlines all downloaded resources into the page.
+fn inline_page(page: &str, base: &Url, resources: &HashMap<Url, Resource>) -> String {
+    let mut edits = Vec::new();
+    for tag in html::tags(page) {
+        if tag.name == "style" {
+            let text = tag.raw_text();
+            let css = inline_css(&page[text.clone()], base, resources);
+            edits.push((text, css));
+            continue;
+        }
+        if let Some((range, url)) = resource_of(&tag) {
+            if let Ok(url) = base.join(&url) {
+                match resources.get(&url) {
+                    // keep the resource reachable from the snapshot's location
+                    None => edits.push((range, url.to_string())),
+                    Some(res) => match tag.name.as_str() {
+                        "link" if res.mime.starts_with("text/css") => {
+                            let css = inline_css(&res.text(), &url, resources);
+                            edits.push((tag.range.clone(), format!("<style>{}</style>", css)));
+                            continue;
+                        }
+                        "script" => {
+                            let script = res.text().replace("</script", "<\\/script");
+                            edits.push((
+                                tag.range.start..tag.raw_text_end(),
+                                format!("<script>{}</script>", script),
+                            ));
+                            continue;
+                        }
+                        _ => edits.push((range, res.data_url())),
+                    },
+                }
+            }
+        }
+        if let Some((range, srcset)) = tag.attr_range("srcset") {
+            for (candidate, url) in srcset_urls(srcset) {
+                if let Ok(url) = base.join(&html::decode_entities(url)) {
+                    let replacement = match resources.get(&url) {
+                        Some(res) => res.data_url(),
+                        None => url.to_string(),
+                    };
+                    edits.push((
+                        range.start + candidate.start..range.start + candidate.end,
+                        replacement,
+                    ));
+                }
+            }
+        }
+        if let (Some((range, _)), Some(style)) = (tag.attr_range("style"), tag.attr("style")) {
+            let inlined = inline_css(&style, base, resources);
+            if inlined != style {
+                edits.push((range, escape_attr(&inlined)));
+            }
+        }
+    }
+    apply_edits(page, edits)
+}
+
+/// Replaces the ranges of `text`, the ranges must not overlap.
+fn apply_edits(text: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut edited = text.to_string();
+    for (range, replacement) in edits.into_iter().rev() {
+        edited.replace_range(range, &replacement);
+    }
+    edited
+}
+
+/// Escapes a value so that it can be placed in a quoted or unquoted html attribute.
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The encoding named by the `charset` parameter of a mime type.
+fn charset(mime: &str) -> Option<&'static Encoding> {
+    mime.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(
+            value
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'')
+                .as_bytes(),
+        )
+    })
+}
+
+/// The encoding the `<meta>` tags within the first 1024 bytes of a page declare.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(1024)]);
+    let encoding = html::tags(&head)
+        .iter()
+        .filter(|tag| tag.name == "meta")
+        .find_map(|tag| {
+            if let Some(label) = tag.attr("charset") {
+                return Encoding::for_label(label.trim().as_bytes());
+            }
+            if tag.attr("http-equiv")?.eq_ignore_ascii_case("content-type") {
+                return charset(&tag.attr("content")?);
+            }
+            None
+        })?;
+    // a page can't declare itself as UTF-16 in ascii, browsers take it as UTF-8
+    Some(encoding.output_encoding())
+}
+
+/// Decodes a page the way browsers do: a byte order mark takes precedence over the charset of
+/// the `Content-Type` header, which takes precedence over the `<meta>` tags. Pages without any
+/// declaration are read as UTF-8.
+fn decode_page(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(charset)
+        .or_else(|| meta_charset(body))
+        .unwrap_or(UTF_8);
+    // `decode` sniffs the byte order mark itself
+    encoding.decode(body).0.into_owned()
+}
+
+/// Changes the charset the `<meta>` tags of a decoded page declare to UTF-8, the encoding the
+/// snapshot is stored in.
+fn declare_utf8(page: &str) -> String {
+    let mut edits = Vec::new();
+    for tag in html::tags(page).iter().filter(|tag| tag.name == "meta") {
+        if let Some((range, _)) = tag.attr_range("charset") {
+            edits.push((range, "utf-8".to_string()));
+        } else if tag
+            .attr("http-equiv")
+            .is_some_and(|equiv| equiv.eq_ignore_ascii_case("content-type"))
+        {
+            if let Some((range, _)) = tag.attr_range("content") {
+                edits.push((range, "text/html;charset=utf-8".to_string()));
+            }
+        }
+    }
+    apply_edits(page, edits)
+}
+
+/// Packs the page and all downloaded resources into a MIME html archive.
+fn mhtml(
+    page: &str,
+    base: &Url,
+    resources: &HashMap<Url, Resource>,
+    date: chrono::DateTime<Utc>,
+) -> String {
+    const BOUNDARY: &str = "----MultipartBoundary--archiveis----";
+
+    // browsers resolve the resources by their absolute url
+    let mut edits = Vec::new();
+    for tag in html::tags(page) {
+        if let Some((range, url)) = resource_of(&tag) {
+            if let Ok(url) = base.join(&url) {
+                edits.push((range, url.to_string()));
+            }
+        }
+    }
+    let page = apply_edits(page, edits);
+
+    let mut out = format!(
+        "From: <Saved by archiveis>\r\n\
+         Snapshot-Content-Location: {}\r\n\
+         Date: {}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/related;\r\n\
+         \ttype=\"text/html\";\r\n\
+         \tboundary=\"{}\"\r\n\r\n",
+        base,
+        date.format("%a, %d %b %Y %H:%M:%S GMT"),
+        BOUNDARY
+    );
+    let mut part = |mime: &str, location: &Url, body: &[u8]| {
+        out.push_str(&format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: base64\r\nContent-Location: {}\r\n\r\n",
+            BOUNDARY, mime, location
+        ));
+        let encoded = STANDARD.encode(body);
+        for line in encoded.as_bytes().chunks(76) {
+            out.push_str(std::str::from_utf8(line).unwrap_or_default());
+            out.push_str("\r\n");
+        }
+        out.push_str("\r\n");
+    };
+    part("text/html; charset=utf-8", base, page.as_bytes());
+    for (url, res) in resources {
+        part(&res.mime, url, &res.body);
+    }
+    out.push_str(&format!("--{}--\r\n", BOUNDARY));
+    out
+}
+
+/// A file name friendly version of the url.
+fn slug(url: &Url) -> String {
+    let raw = format!(
+        "{}{}",
+        url.host_str().unwrap_or_default(),
+        url.path().trim_end_matches('/')
+    );
+    let mut slug: String = raw
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(SLUG_LENGTH)
+        .collect();
+    // distinguish urls that only differ in their query, in the truncated part or in replaced
+    // characters without making the name unreadable
+    let hash = url
+        .as_str()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    slug.push_str(&format!("_{:08x}", hash as u32));
+    slug
+}
+
+/// The time stamp and counter of a snapshot file name without its slug, like
+/// `20200101120000123` or `20200101120000123-2` for the second snapshot in the same millisecond.
+fn snapshot_time(name: &str) -> Option<(NaiveDateTime, u32)> {
+    let (stamp, counter) = match name.split_once('-') {
+        Some((stamp, counter)) => (stamp, counter.parse().ok()?),
+        None => (name, 1),
+    };
+    NaiveDateTime::parse_from_str(stamp, TIME_STAMP_FORMAT)
+        .ok()
+        .map(|time| (time, counter))
+}
+
+/// The `file://` url of a snapshot.
+fn file_url(path: &Path) -> Result<String> {
+    let path = fs::canonicalize(path)?;
+    Url::from_file_path(&path)
+        .map(String::from)
+        .map_err(|_| Error::MissingUrl(path.display().to_string()))
+}
+
+/// Guesses the mime type of a resource without `Content-Type` header from its extension.
+fn guess_mime(url: &Url) -> &'static str {
+    let ext = url
+        .path()
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match ext.as_str() {
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(mime: &str, body: &str) -> Resource {
+        Resource {
+            mime: mime.to_string(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn inline_resources() {
+        let base: Url = "http://example.com/dir/page.html".parse().unwrap();
+        let page = r#"<html><head><link rel="stylesheet" href="style.css"><script src="/app.js"></script></head>
+<body><img src="img.png"><img src="missing.png"></body></html>"#;
+        assert_eq!(page_resources(page, &base).len(), 4);
+
+        let mut resources = HashMap::new();
+        resources.insert(
+            base.join("style.css").unwrap(),
+            resource("text/css", "body { background: url('bg.png') }"),
+        );
+        resources.insert(base.join("bg.png").unwrap(), resource("image/png", "bg"));
+        resources.insert(
+            base.join("/app.js").unwrap(),
+            resource("text/javascript", "alert('</script>')"),
+        );
+        resources.insert(base.join("img.png").unwrap(), resource("image/png", "img"));
+
+        let inlined = inline_page(page, &base, &resources);
+        assert!(inlined
+            .contains(r#"<style>body { background: url("data:image/png;base64,Ymc=") }</style>"#));
+        assert!(inlined.contains(r#"<script>alert('<\/script>')</script>"#));
+        assert!(inlined.contains(r#"<img src="data:image/png;base64,aW1n">"#));
+        assert!(inlined.contains(r#"<img src="http://example.com/dir/missing.png">"#));
+    }
+
+    #[test]
+    fn inline_styles_and_srcset() {
+        let base: Url = "http://example.com/".parse().unwrap();
+        let page = r#"<style>h1 { background: url(bg.png) }</style>
+<div style="background: url('bg.png')"></div>
+<img srcset="small.png 1x, missing.png 2x"><picture><source srcset="big.png 800w,small.png 400w"></picture>"#;
+        let urls = page_resources(page, &base);
+        assert_eq!(urls.len(), 4);
+
+        let mut resources = HashMap::new();
+        for name in &["bg.png", "small.png", "big.png"] {
+            resources.insert(base.join(name).unwrap(), resource("image/png", "img"));
+        }
+        let data = "data:image/png;base64,aW1n";
+        let inlined = inline_page(page, &base, &resources);
+        assert!(inlined.contains(&format!(
+            r#"<style>h1 {{ background: url("{}") }}</style>"#,
+            data
+        )));
+        assert!(inlined.contains(&format!(
+            r#"<div style="background: url(&quot;{}&quot;)">"#,
+            data
+        )));
+        assert!(inlined.contains(&format!(
+            r#"<img srcset="{} 1x, http://example.com/missing.png 2x">"#,
+            data
+        )));
+        assert!(inlined.contains(&format!(r#"<source srcset="{} 800w,{} 400w">"#, data, data)));
+    }
+
+    #[test]
+    fn decode_charsets() {
+        let latin1 = b"<html><head><meta charset=\"iso-8859-1\"></head><body>caf\xe9</body>";
+        let page = decode_page(latin1, None);
+        assert!(page.contains("caf\u{e9}"));
+        assert!(declare_utf8(&page).contains(r#"<meta charset="utf-8">"#));
+        let page = decode_page(b"caf\xe9", Some("text/html; charset=windows-1252"));
+        assert_eq!(page, "caf\u{e9}");
+        assert_eq!(decode_page("caf\u{e9}".as_bytes(), None), "caf\u{e9}");
+
+        let page = r#"<meta http-equiv="Content-Type" content="text/html; charset=iso-8859-1">"#;
+        assert!(declare_utf8(page).contains(r#"content="text/html;charset=utf-8""#));
+        let res = resource("text/css; charset=iso-8859-1", "");
+        assert_eq!(
+            Resource {
+                body: b"\xe9".to_vec(),
+                ..res
+            }
+            .text(),
+            "\u{e9}"
+        );
+    }
+
+    #[test]
+    fn mhtml_parts() {
+        let base: Url = "http://example.com/".parse().unwrap();
+        let mut resources = HashMap::new();
+        resources.insert(base.join("img.png").unwrap(), resource("image/png", "img"));
+        let archive = mhtml(r#"<img src="img.png">"#, &base, &resources, Utc::now());
+        assert!(archive.contains("Content-Location: http://example.com/img.png"));
+        assert_eq!(
+            archive.matches("Content-Transfer-Encoding: base64").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn snapshot_names() {
+        let url: Url = "https://example.com/a/b/?q=1".parse().unwrap();
+        assert!(slug(&url).starts_with("example.com_a_b_"));
+        assert_ne!(
+            slug(&url),
+            slug(&"https://example.com/a/b/?q=2".parse().unwrap())
+        );
+        let long = format!("https://example.com/{}", "a".repeat(200));
+        assert_ne!(
+            slug(&format!("{}/1", long).parse().unwrap()),
+            slug(&format!("{}/2", long).parse().unwrap())
+        );
+        assert_ne!(
+            slug(&"https://example.com/a-b".parse().unwrap()),
+            slug(&"https://example.com/a_b".parse().unwrap())
+        );
+
+        let (time, counter) = snapshot_time("20200101120000123").unwrap();
+        assert_eq!(time.to_string(), "2020-01-01 12:00:00.123");
+        assert_eq!(counter, 1);
+        assert!(snapshot_time("20200101120000123-2").unwrap() > (time, counter));
+        assert_eq!(snapshot_time("20200101120000123-x"), None);
+    }
+}
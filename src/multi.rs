@@ -0,0 +1,514 @@
+//! Capturing urls with several archive services at once.
+
+#[cfg(feature = "filter")]
+use crate::filter::{LinkFilter, Rejection};
+use crate::{
+    normalize::{Normalized, Normalizer},
+    Archived, Archiver, Error, Provider, Result,
+};
+use futures::{
+    future,
+    stream::{self, FuturesUnordered},
+    StreamExt,
+};
+use std::{fmt, str::FromStr};
+use url::Url;
+
+/// Decides which of the backends of a `MultiArchiver` capture a url
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Capture the url with every backend concurrently
+    #[default]
+    All,
+    /// Capture the url with every backend concurrently, but stop as soon as the first capture
+    /// succeeded
+    FirstSuccess,
+    /// Try the backends one after another in order, until a capture succeeded
+    Fallback,
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::All => write!(f, "all"),
+            Policy::FirstSuccess => write!(f, "first-success"),
+            Policy::Fallback => write!(f, "fallback"),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Policy::All),
+            "first-success" | "first" => Ok(Policy::FirstSuccess),
+            "fallback" => Ok(Policy::Fallback),
+            _ => Err(format!("Unknown policy {}", s)),
+        }
+    }
+}
+
+/// The results of capturing a single url with several archive services
+#[derive(Debug)]
+pub struct MultiArchived {
+    /// The requested url, normalized if the `MultiArchiver` normalizes urls
+    pub target_url: Url,
+    /// The urls that were given to capture `target_url`, several if duplicates were collapsed
+    pub originals: Vec<Url>,
+    /// The result of every backend that was invoked, in the order they finished
+    pub results: Vec<(Provider, Result<Archived>)>,
+}
+
+impl MultiArchived {
+    /// All successful captures
+    pub fn successes(&self) -> impl Iterator<Item = &Archived> {
+        self.results.iter().filter_map(|(_, res)| res.as_ref().ok())
+    }
+
+    /// All failed captures together with the provider that failed
+    pub fn failures(&self) -> impl Iterator<Item = (&Provider, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(provider, res)| res.as_ref().err().map(|err| (provider, err)))
+    }
+
+    /// Whether the url was captured as requested by `policy`.
+    ///
+    /// With `Policy::All` every backend has to succeed, the other policies require a single
+    /// successful capture.
+    pub fn is_complete(&self, policy: Policy) -> bool {
+        match policy {
+            Policy::All => self.results.iter().all(|(_, res)| res.is_ok()),
+            Policy::FirstSuccess | Policy::Fallback => self.successes().next().is_some(),
+        }
+    }
+}
+
+/// What `MultiArchiver::capture_all` would capture for a set of urls
+#[cfg(feature = "filter")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// The urls that would be captured, normalized and without duplicates
+    pub targets: Vec<Normalized>,
+    /// The urls the filter rejects, together with the reason
+    pub rejected: Vec<(Url, Rejection)>,
+}
+
+/// Captures urls with a set of `Archiver` backends, like archive.is, the Wayback Machine or any
+/// custom implementation.
+///
+/// ```no_run
+/// # use archiveis::{ArchiveClient, MultiArchiver, Policy, WaybackClient};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let archiver = MultiArchiver::new(Policy::All)
+///     .with(ArchiveClient::default())
+///     .with(WaybackClient::default());
+/// let captured = archiver.capture(&"http://example.com/".parse()?).await;
+/// for archived in captured.successes() {
+///     println!("{}: {}", archived.provider, archived.archived_url);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct MultiArchiver {
+    /// The backends in the order they were added
+    archivers: Vec<Box<dyn Archiver + Send + Sync>>,
+    /// How the backends are invoked
+    policy: Policy,
+    /// Normalizes urls before they are captured
+    normalizer: Option<Normalizer>,
+    /// Decides which urls may be captured
+    #[cfg(feature = "filter")]
+    filter: Option<LinkFilter>,
+}
+
+impl MultiArchiver {
+    /// Creates a new `MultiArchiver` without any backends
+    pub fn new(policy: Policy) -> Self {
+        MultiArchiver {
+            archivers: Vec::new(),
+            policy,
+            normalizer: None,
+            #[cfg(feature = "filter")]
+            filter: None,
+        }
+    }
+
+    /// Normalizes every url with `normalizer` before it is captured, `capture_all` captures
+    /// urls that are the same after normalizing only once
+    pub fn normalize(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// The normalizer urls are normalized with, if any
+    pub fn normalizer(&self) -> Option<&Normalizer> {
+        self.normalizer.as_ref()
+    }
+
+    /// Captures only the urls accepted by `filter`, which checks the normalized urls
+    #[cfg(feature = "filter")]
+    pub fn filter(mut self, filter: LinkFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// The filter urls are checked with, if any
+    #[cfg(feature = "filter")]
+    pub fn link_filter(&self) -> Option<&LinkFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Checks whether `url` may be captured according to the filter
+    #[cfg(feature = "filter")]
+    pub fn check(&self, url: &Url) -> std::result::Result<(), Rejection> {
+        match &self.filter {
+            Some(filter) => filter.check(&self.target_url(url)),
+            None => Ok(()),
+        }
+    }
+
+    /// What `capture_all` would capture of `urls`, without capturing anything
+    #[cfg(feature = "filter")]
+    pub fn plan(&self, urls: &[Url]) -> Plan {
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        for url in urls {
+            match self.check(url) {
+                Ok(()) => accepted.push(url.clone()),
+                Err(rejection) => rejected.push((url.clone(), rejection)),
+            }
+        }
+        Plan {
+            targets: self.dedup(accepted),
+            rejected,
+        }
+    }
+
+    /// The normalized `urls` without duplicates
+    fn dedup(&self, urls: Vec<Url>) -> Vec<Normalized> {
+        match &self.normalizer {
+            Some(normalizer) => normalizer.dedup(urls),
+            None => urls
+                .into_iter()
+                .map(|url| Normalized {
+                    url: url.clone(),
+                    originals: vec![url],
+                })
+                .collect(),
+        }
+    }
+
+    /// The url that is captured for `url`
+    pub fn target_url(&self, url: &Url) -> Url {
+        match &self.normalizer {
+            Some(normalizer) => normalizer.normalize(url),
+            None => url.clone(),
+        }
+    }
+
+    /// Adds another backend
+    pub fn with<A: Archiver + Send + Sync + 'static>(mut self, archiver: A) -> Self {
+        self.archivers.push(Box::new(archiver));
+        self
+    }
+
+    /// Adds another boxed backend
+    pub fn push(&mut self, archiver: Box<dyn Archiver + Send + Sync>) {
+        self.archivers.push(archiver);
+    }
+
+    /// The policy backends are invoked with
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// The providers of all backends, in order
+    pub fn providers(&self) -> Vec<Provider> {
+        self.archivers.iter().map(|a| a.provider()).collect()
+    }
+
+    /// Captures `url` with the backends as requested by the policy.
+    ///
+    /// If the filter rejects `url`, no backend is invoked and every backend fails with
+    /// `Error::Rejected`.
+    pub async fn capture(&self, url: &Url) -> MultiArchived {
+        let target = self.target_url(url);
+        #[cfg(feature = "filter")]
+        {
+            if let Err(rejection) = self.check(url) {
+                let results = self
+                    .providers()
+                    .into_iter()
+                    .map(|provider| {
+                        let err = Error::Rejected(format!("{} {}", target, rejection));
+                        (provider, Err(err))
+                    })
+                    .collect();
+                return MultiArchived {
+                    target_url: target,
+                    originals: vec![url.clone()],
+                    results,
+                };
+            }
+        }
+        let mut captured = self.capture_target(&target).await;
+        captured.originals = vec![url.clone()];
+        captured
+    }
+
+    /// Captures the already normalized `url`
+    async fn capture_target(&self, url: &Url) -> MultiArchived {
+        let results = match self.policy {
+            Policy::All => {
+                future::join_all(self.archivers.iter().map(|archiver| async move {
+                    (archiver.provider(), archiver.capture(url).await)
+                }))
+                .await
+            }
+            Policy::FirstSuccess => {
+                let mut captures =
+                    self.archivers
+                        .iter()
+                        .map(|archiver| async move {
+                            (archiver.provider(), archiver.capture(url).await)
+                        })
+                        .collect::<FuturesUnordered<_>>();
+                let mut results = Vec::with_capacity(self.archivers.len());
+                while let Some((provider, res)) = captures.next().await {
+                    let done = res.is_ok();
+                    results.push((provider, res));
+                    if done {
+                        break;
+                    }
+                }
+                results
+            }
+            Policy::Fallback => {
+                let mut results = Vec::with_capacity(self.archivers.len());
+                for archiver in &self.archivers {
+                    let res = archiver.capture(url).await;
+                    let done = res.is_ok();
+                    results.push((archiver.provider(), res));
+                    if done {
+                        break;
+                    }
+                }
+                results
+            }
+        };
+        MultiArchived {
+            target_url: url.clone(),
+            originals: vec![url.clone()],
+            results,
+        }
+    }
+
+    /// Looks up the latest existing snapshot of `url` with every backend concurrently, without
+    /// capturing it.
+    ///
+    /// The results are in the order of the backends.
+    pub async fn lookup(&self, url: &Url) -> Vec<(Provider, Result<Option<Archived>>)> {
+        let target = &self.target_url(url);
+        future::join_all(
+            self.archivers.iter().map(|archiver| async move {
+                (archiver.provider(), archiver.lookup(target).await)
+            }),
+        )
+        .await
+    }
+
+    /// Captures every url, running up to 10 urls concurrently.
+    ///
+    /// The returned `Vec` holds the results of every url, so every single capture request gets
+    /// executed regardless of the success of prior requests. With a normalizer, urls that are
+    /// the same after normalizing are captured once, the `originals` of the capture list them.
+    /// Urls rejected by the filter are skipped, `plan` lists them.
+    pub async fn capture_all(&self, urls: &[Url]) -> Vec<MultiArchived> {
+        #[cfg(feature = "filter")]
+        let targets = self.plan(urls).targets;
+        #[cfg(not(feature = "filter"))]
+        let targets = self.dedup(urls.to_vec());
+        stream::iter(targets.into_iter().map(|target| async move {
+            let mut captured = self.capture_target(&target.url).await;
+            captured.originals = target.originals;
+            captured
+        }))
+        .buffer_unordered(10)
+        .collect()
+        .await
+    }
+
+    /// Tries again to complete a previous capture of the url.
+    ///
+    /// With `Policy::All` only the backends that failed are invoked again, the other policies
+    /// capture the url anew if none of the backends succeeded.
+    pub async fn retry(&self, previous: MultiArchived) -> MultiArchived {
+        if previous.is_complete(self.policy) {
+            return previous;
+        }
+        match self.policy {
+            Policy::All => {
+                let MultiArchived {
+                    target_url,
+                    originals,
+                    results,
+                } = previous;
+                let target = &target_url;
+                let results = future::join_all(results.into_iter().map(|(provider, res)| {
+                    let archiver = self.archivers.iter().find(|a| a.provider() == provider);
+                    async move {
+                        match (res, archiver) {
+                            (Err(_), Some(archiver)) => (provider, archiver.capture(target).await),
+                            (res, _) => (provider, res),
+                        }
+                    }
+                }))
+                .await;
+                MultiArchived {
+                    target_url,
+                    originals,
+                    results,
+                }
+            }
+            Policy::FirstSuccess | Policy::Fallback => {
+                let mut captured = self.capture_target(&previous.target_url).await;
+                captured.originals = previous.originals;
+                captured
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct Mock {
+        name: &'static str,
+        fails: bool,
+    }
+
+    #[async_trait]
+    impl Archiver for Mock {
+        fn provider(&self) -> Provider {
+            Provider::Other(self.name.to_string())
+        }
+
+        async fn capture(&self, url: &Url) -> Result<Archived> {
+            if self.fails {
+                return Err(Error::ServerError(url.to_string()));
+            }
+            Ok(Archived {
+                target_url: url.to_string(),
+                archived_url: format!("https://{}/{}", self.name, url),
+                time_stamp: None,
+                submit_token: String::new(),
+                provider: self.provider(),
+            })
+        }
+
+        async fn lookup(&self, _url: &Url) -> Result<Option<Archived>> {
+            Ok(None)
+        }
+    }
+
+    fn archiver(policy: Policy) -> MultiArchiver {
+        MultiArchiver::new(policy)
+            .with(Mock {
+                name: "broken",
+                fails: true,
+            })
+            .with(Mock {
+                name: "first",
+                fails: false,
+            })
+            .with(Mock {
+                name: "second",
+                fails: false,
+            })
+    }
+
+    #[test]
+    fn policies() {
+        let url: Url = "http://example.com/".parse().unwrap();
+        futures::executor::block_on(async {
+            let all = archiver(Policy::All).capture(&url).await;
+            assert_eq!(all.results.len(), 3);
+            assert_eq!(all.successes().count(), 2);
+            assert!(!all.is_complete(Policy::All));
+
+            let fallback = archiver(Policy::Fallback).capture(&url).await;
+            assert_eq!(fallback.results.len(), 2);
+            assert_eq!(
+                fallback.successes().next().unwrap().provider,
+                Provider::Other("first".to_string())
+            );
+            assert!(fallback.is_complete(Policy::Fallback));
+
+            let first = archiver(Policy::FirstSuccess).capture(&url).await;
+            assert_eq!(first.successes().count(), 1);
+        });
+    }
+
+    #[test]
+    fn collapse_duplicates() {
+        let urls: Vec<Url> = [
+            "http://example.com/",
+            "https://example.com/#top",
+            "https://example.com/?utm_source=feed",
+            "https://example.org/",
+        ]
+        .iter()
+        .map(|url| url.parse().unwrap())
+        .collect();
+        let archiver = archiver(Policy::Fallback).normalize(Normalizer::new().upgrade_scheme(true));
+        let mut captured = futures::executor::block_on(archiver.capture_all(&urls));
+        captured.sort_by(|a, b| a.target_url.cmp(&b.target_url));
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[0].target_url.as_str(), "https://example.com/");
+        assert_eq!(captured[0].originals, urls[..3].to_vec());
+        assert_eq!(
+            captured[0].successes().next().unwrap().target_url,
+            "https://example.com/"
+        );
+        assert_eq!(captured[1].originals, urls[3..].to_vec());
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn skip_rejected() {
+        use crate::filter::LinkFilter;
+
+        let urls: Vec<Url> = [
+            "https://example.com/",
+            "http://localhost:8080/#top",
+            "http://localhost:8080/",
+        ]
+        .iter()
+        .map(|url| url.parse().unwrap())
+        .collect();
+        let archiver = archiver(Policy::Fallback)
+            .normalize(Normalizer::new())
+            .filter(LinkFilter::new().reject_private(true));
+        let plan = archiver.plan(&urls);
+        assert_eq!(plan.targets.len(), 1);
+        assert_eq!(plan.rejected.len(), 2);
+        assert_eq!(plan.rejected[0].1, Rejection::Private);
+
+        let captured = futures::executor::block_on(archiver.capture_all(&urls));
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].target_url, urls[0]);
+
+        let rejected = futures::executor::block_on(archiver.capture(&urls[1]));
+        assert_eq!(rejected.successes().count(), 0);
+        assert!(rejected
+            .failures()
+            .all(|(_, err)| matches!(err, Error::Rejected(_))));
+    }
+}
@@ -0,0 +1,227 @@
+//! Normalizing urls, so that variants of the same url are captured only once.
+//!
+//! ```
+//! use archiveis::normalize::Normalizer;
+//! use url::Url;
+//!
+//! let normalizer = Normalizer::new().upgrade_scheme(true);
+//! let urls: Vec<Url> = ["http://x.com/", "https://X.com:443", "https://x.com/?utm_source=feed#top"]
+//!     .iter()
+//!     .map(|url| url.parse().unwrap())
+//!     .collect();
+//! let deduped = normalizer.dedup(urls);
+//! assert_eq!(deduped.len(), 1);
+//! assert_eq!(deduped[0].url.as_str(), "https://x.com/");
+//! assert_eq!(deduped[0].originals.len(), 3);
+//! ```
+
+use std::collections::HashMap;
+use url::Url;
+
+/// The query parameters that only track where a visitor came from, a trailing `*` matches every
+/// parameter with that prefix
+const TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "gclsrc", "msclkid", "yclid", "igshid", "mc_cid",
+    "mc_eid", "_hsenc", "_hsmi", "mkt_tok",
+];
+
+/// Normalizes urls.
+///
+/// Parsing a `Url` already lowercases the host, encodes international domain names as punycode
+/// and removes the default port of the scheme. By default the `Normalizer` also removes a
+/// trailing dot of the host, the fragment and the tracking parameters of the query, like
+/// `utm_source` or `fbclid`. Upgrading `http` to `https` and removing the trailing slash of the
+/// path change which page is requested, they are only applied if enabled.
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    upgrade_scheme: bool,
+    strip_trailing_slash: bool,
+    remove_fragment: bool,
+    strip_tracking: bool,
+    tracking_params: Vec<String>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer {
+            upgrade_scheme: false,
+            strip_trailing_slash: false,
+            remove_fragment: true,
+            strip_tracking: true,
+            tracking_params: TRACKING_PARAMS.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+/// A normalized url together with all the urls that were normalized to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    /// The normalized url
+    pub url: Url,
+    /// The original urls in the order they were given, including duplicates
+    pub originals: Vec<Url>,
+}
+
+impl Normalizer {
+    /// Creates a new `Normalizer` with the default rules
+    pub fn new() -> Self {
+        Normalizer::default()
+    }
+
+    /// Whether `http` urls are upgraded to `https`
+    pub fn upgrade_scheme(mut self, upgrade: bool) -> Self {
+        self.upgrade_scheme = upgrade;
+        self
+    }
+
+    /// Whether a trailing slash is removed from the path, like `/page/` becomes `/page`
+    pub fn strip_trailing_slash(mut self, strip: bool) -> Self {
+        self.strip_trailing_slash = strip;
+        self
+    }
+
+    /// Whether the fragment, like `#section`, is removed
+    pub fn remove_fragment(mut self, remove: bool) -> Self {
+        self.remove_fragment = remove;
+        self
+    }
+
+    /// Whether tracking parameters are removed from the query
+    pub fn strip_tracking(mut self, strip: bool) -> Self {
+        self.strip_tracking = strip;
+        self
+    }
+
+    /// Removes the query parameter `name` as well, a trailing `*` matches every parameter with
+    /// that prefix
+    pub fn tracking_param<T: ToString>(mut self, name: T) -> Self {
+        self.tracking_params.push(name.to_string());
+        self
+    }
+
+    /// Whether the query parameter `name` is removed
+    fn is_tracking(&self, name: &str) -> bool {
+        self.tracking_params
+            .iter()
+            .any(|param| match param.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == param,
+            })
+    }
+
+    /// The normalized `url`
+    pub fn normalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        if let Some(host) = url.host_str().and_then(|host| host.strip_suffix('.')) {
+            let host = host.to_string();
+            let _ = url.set_host(Some(&host));
+        }
+        if self.upgrade_scheme && url.scheme() == "http" {
+            let port = url.port();
+            let _ = url.set_scheme("https");
+            if port == Some(443) {
+                let _ = url.set_port(None);
+            }
+        }
+        if self.remove_fragment {
+            url.set_fragment(None);
+        }
+        if let Some(query) = url.query() {
+            // filter the raw pairs to keep the encoding of the remaining ones
+            let query: Vec<_> = query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .filter(|pair| {
+                    let name = pair.split('=').next().unwrap_or_default();
+                    let name = url::form_urlencoded::parse(name.as_bytes())
+                        .next()
+                        .map(|(name, _)| name.into_owned())
+                        .unwrap_or_default();
+                    !(self.strip_tracking && self.is_tracking(&name))
+                })
+                .map(str::to_string)
+                .collect();
+            if query.is_empty() {
+                url.set_query(None);
+            } else {
+                url.set_query(Some(&query.join("&")));
+            }
+        }
+        if self.strip_trailing_slash && url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(&path);
+        }
+        url
+    }
+
+    /// Normalizes all `urls` and collapses duplicates, in the order each normalized url was
+    /// first found
+    pub fn dedup<I: IntoIterator<Item = Url>>(&self, urls: I) -> Vec<Normalized> {
+        let mut normalized: Vec<Normalized> = Vec::new();
+        let mut index: HashMap<Url, usize> = HashMap::new();
+        for original in urls {
+            let url = self.normalize(&original);
+            match index.get(&url) {
+                Some(&idx) => normalized[idx].originals.push(original),
+                None => {
+                    index.insert(url.clone(), normalized.len());
+                    normalized.push(Normalized {
+                        url,
+                        originals: vec![original],
+                    });
+                }
+            }
+        }
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(normalizer: &Normalizer, url: &str) -> String {
+        normalizer.normalize(&url.parse().unwrap()).to_string()
+    }
+
+    #[test]
+    fn normalize_urls() {
+        let normalizer = Normalizer::new();
+        assert_eq!(
+            normalized(
+                &normalizer,
+                "HTTP://Example.COM.:80/a/?utm_source=x&id=1&UTM=2&fbclid=abc#top"
+            ),
+            "http://example.com/a/?id=1&UTM=2"
+        );
+        assert_eq!(
+            normalized(&normalizer, "https://example.com/?q=a%20b&utm_medium=email"),
+            "https://example.com/?q=a%20b"
+        );
+        assert_eq!(
+            normalized(&normalizer, "https://bücher.example/"),
+            "https://xn--bcher-kva.example/"
+        );
+
+        let normalizer = Normalizer::new()
+            .upgrade_scheme(true)
+            .strip_trailing_slash(true)
+            .remove_fragment(false)
+            .tracking_param("ref");
+        assert_eq!(
+            normalized(&normalizer, "http://example.com:443/a/?ref=home#top"),
+            "https://example.com/a#top"
+        );
+        assert_eq!(
+            normalized(&normalizer, "http://example.com:8080/"),
+            "https://example.com:8080/"
+        );
+        assert_eq!(
+            normalized(
+                &Normalizer::new().strip_tracking(false),
+                "https://x.com/?utm_source=a"
+            ),
+            "https://x.com/?utm_source=a"
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Parsers for the HTTP responses of the archive services.
+
+// without an http client only the date parsers are used
+#![cfg_attr(
+    not(any(
+        feature = "runtime-tokio",
+        feature = "runtime-async-std",
+        feature = "blocking"
+    )),
+    allow(dead_code)
+)]
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// The content of a `Refresh` header: `delay; url=...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refresh {
+    /// Seconds to wait before the redirect
+    pub delay: u64,
+    /// The redirect target, if any
+    pub url: Option<String>,
+}
+
+/// Parses the value of a `Refresh` header the way browsers do.
+///
+/// The value starts with a non-negative delay (fractional digits are
+/// ignored), optionally followed by `;` or `,` and the target url. The url
+/// may be preceded by a case-insensitive `url=` and may be enclosed in single
+/// or double quotes. Everything after the `url=` is kept verbatim, so urls
+/// that contain `=` or `;` themselves are not truncated.
+pub fn parse_refresh(value: &str) -> Option<Refresh> {
+    let value = value.trim_start();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (delay, rest) = value.split_at(digits);
+    // a value like `.5; url=...` has no integer part but is still valid
+    if delay.is_empty() && !rest.starts_with('.') {
+        return None;
+    }
+    let delay = if delay.is_empty() {
+        0
+    } else {
+        delay.parse().ok()?
+    };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let rest = rest.trim_start();
+    let rest = match rest.chars().next() {
+        None => return Some(Refresh { delay, url: None }),
+        Some(';') | Some(',') => rest[1..].trim_start(),
+        // the delay has to be followed by a separator
+        Some(_) => return None,
+    };
+
+    let rest = strip_url_prefix(rest).unwrap_or(rest);
+    let url = match rest.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let quoted = &rest[1..];
+            quoted.split(quote).next().unwrap_or(quoted)
+        }
+        _ => rest.trim_end(),
+    };
+
+    Some(Refresh {
+        delay,
+        url: if url.is_empty() {
+            None
+        } else {
+            Some(url.to_string())
+        },
+    })
+}
+
+/// Strips a leading case-insensitive `url` followed by `=`, allowing
+/// whitespace around the `=`.
+fn strip_url_prefix(value: &str) -> Option<&str> {
+    let prefix = value.get(..3)?;
+    if !prefix.eq_ignore_ascii_case("url") {
+        return None;
+    }
+    let rest = value[3..].trim_start();
+    if !rest.starts_with('=') {
+        return None;
+    }
+    Some(rest[1..].trim_start())
+}
+
+/// Parses an HTTP-date as specified in RFC 7231 section 7.1.1.1.
+///
+/// All three formats a recipient has to accept are supported:
+///
+/// * IMF-fixdate: `Sun, 06 Nov 1994 08:49:37 GMT`
+/// * the obsolete RFC 850 format: `Sunday, 06-Nov-94 08:49:37 GMT`
+/// * ANSI C's asctime() format: `Sun Nov  6 08:49:37 1994`
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    const FORMATS: [&str; 3] = [
+        // IMF-fixdate
+        "%a, %d %b %Y %H:%M:%S GMT",
+        // RFC 850, two digit years are mapped to 1970-2069
+        "%A, %d-%b-%y %H:%M:%S GMT",
+        // asctime
+        "%a %b %e %H:%M:%S %Y",
+    ];
+    let value = value.trim();
+    FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .map(|date| date.and_utc())
+}
+
+/// Parses a date in the W3C datetime format used by sitemaps, like `2005-01-01` or
+/// `2004-12-23T18:00:15+00:00`.
+///
+/// Dates without time are taken as midnight UTC.
+pub fn parse_w3c_datetime(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    // the seconds are optional
+    if let Ok(date) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M%:z") {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%MZ") {
+        return Some(date.and_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+}
+
+/// Extracts the `submitid` token from the html of the archive.is front page.
+pub fn parse_submit_token(html: &str) -> Option<String> {
+    html.rsplit("name=\"submitid")
+        .next()
+        .and_then(|x| x.split_once("value=\""))
+        .and_then(|(_, token)| token.split('\"').next().map(str::to_string))
+}
+
+/// Extracts the url of an archived page from its `og:url` meta tag.
+pub fn parse_og_url(html: &str) -> Option<String> {
+    html.split_once("<meta property=\"og:url\"")
+        .and_then(|(_, x)| x.split_once("content=\""))
+        .and_then(|(_, id)| id.split('\"').next().map(str::to_owned))
+}
+
+/// A single snapshot listed in a Memento timemap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memento {
+    /// The url of the snapshot
+    pub url: String,
+    /// When the snapshot was taken
+    pub datetime: Option<DateTime<Utc>>,
+}
+
+/// Parses a timemap in the `application/link-format` (RFC 7089) that both archive.is and the
+/// Wayback Machine serve, and returns all mementos in the order they are listed.
+pub fn parse_timemap(body: &str) -> Vec<Memento> {
+    let mut mementos = Vec::new();
+    let mut rest = body;
+    while let Some((_, link)) = rest.split_once('<') {
+        let (url, tail) = match link.split_once('>') {
+            Some(link) => link,
+            None => break,
+        };
+        // the parameters of a link run until the next link starts
+        let (params, next) = tail.split_at(tail.find('<').unwrap_or(tail.len()));
+        rest = next;
+
+        let rel = link_param(params, "rel").unwrap_or_default();
+        if !rel.split_whitespace().any(|rel| rel == "memento") {
+            continue;
+        }
+        mementos.push(Memento {
+            url: url.to_string(),
+            datetime: link_param(params, "datetime").and_then(parse_http_date),
+        });
+    }
+    mementos
+}
+
+/// Returns the value of the parameter `name` of a single link in link-format.
+fn link_param<'a>(params: &'a str, name: &str) -> Option<&'a str> {
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim().trim_end_matches(',').trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Extracts the 14 digit time stamp of a Wayback Machine url like
+/// `https://web.archive.org/web/20200101120000/http://example.com/`.
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+pub fn parse_wayback_timestamp(url: &str) -> Option<DateTime<Utc>> {
+    let (_, rest) = url.split_once("/web/")?;
+    let stamp = rest.get(..14)?;
+    NaiveDateTime::parse_from_str(stamp, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    #[test]
+    fn submit_token_and_og_url() {
+        let html = r#"<input type="hidden" name="submitid" value="abc+def"/>"#;
+        assert_eq!(parse_submit_token(html), Some("abc+def".to_string()));
+        let html = r#"<meta property="og:url" content="http://archive.is/abcd"/>"#;
+        assert_eq!(
+            parse_og_url(html),
+            Some("http://archive.is/abcd".to_string())
+        );
+        assert_eq!(parse_og_url("<html></html>"), None);
+    }
+
+    #[test]
+    fn refresh_keeps_equal_signs() {
+        let refresh =
+            parse_refresh("0;url=http://archive.is/wip/abcd?url=https://example.com/?a=b&c=d")
+                .unwrap();
+        assert_eq!(refresh.delay, 0);
+        assert_eq!(
+            refresh.url.as_deref(),
+            Some("http://archive.is/wip/abcd?url=https://example.com/?a=b&c=d")
+        );
+    }
+
+    #[test]
+    fn refresh_variants() {
+        let url = Some("http://archive.is/abcd".to_string());
+        for value in &[
+            "0;url=http://archive.is/abcd",
+            "0; URL=http://archive.is/abcd",
+            "0 ; Url = \"http://archive.is/abcd\"",
+            "0,url='http://archive.is/abcd'",
+            "0.5; http://archive.is/abcd",
+            " 0;url=http://archive.is/abcd  ",
+        ] {
+            assert_eq!(parse_refresh(value).unwrap().url, url, "{}", value);
+        }
+        assert_eq!(
+            parse_refresh("5"),
+            Some(Refresh {
+                delay: 5,
+                url: None
+            })
+        );
+        assert_eq!(parse_refresh("url=http://archive.is/abcd"), None);
+        assert_eq!(parse_refresh(""), None);
+    }
+
+    #[test]
+    fn http_date_formats() {
+        let expected = Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap();
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+            Some(expected)
+        );
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(expected));
+        assert_eq!(parse_http_date("06 Nov 1994"), None);
+    }
+
+    #[test]
+    fn w3c_datetimes() {
+        let expected = Utc.with_ymd_and_hms(2004, 12, 23, 18, 0, 0).unwrap();
+        assert_eq!(
+            parse_w3c_datetime("2004-12-23T18:00:00+00:00"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_w3c_datetime("2004-12-23T19:00:00.0+01:00"),
+            Some(expected)
+        );
+        assert_eq!(parse_w3c_datetime("2004-12-23T18:00Z"), Some(expected));
+        assert_eq!(parse_w3c_datetime("2004-12-23T20:00+02:00"), Some(expected));
+        assert_eq!(
+            parse_w3c_datetime(" 2004-12-23 "),
+            Some(Utc.with_ymd_and_hms(2004, 12, 23, 0, 0, 0).unwrap())
+        );
+        assert_eq!(parse_w3c_datetime("December 2004"), None);
+    }
+
+    #[test]
+    fn timemap_mementos() {
+        let timemap = r#"<http://example.com/>; rel="original",
+<http://archive.is/timegate/http://example.com/>; rel="timegate",
+<http://archive.is/20130101/http://example.com/>; rel="first memento"; datetime="Tue, 01 Jan 2013 00:00:00 GMT",
+<http://archive.is/abcd>; rel="memento"; datetime="Wed, 02 Jan 2013 00:00:00 GMT",
+<http://archive.is/efgh>; rel="last memento"; datetime="Sun, 06 Nov 1994 08:49:37 GMT",
+<http://archive.is/timemap/http://example.com/>; rel="self"; type="application/link-format""#;
+        let mementos = parse_timemap(timemap);
+        assert_eq!(mementos.len(), 3);
+        assert_eq!(mementos[1].url, "http://archive.is/abcd");
+        assert_eq!(
+            mementos[2].datetime,
+            Some(Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap())
+        );
+        assert!(parse_timemap("").is_empty());
+    }
+
+    #[test]
+    fn wayback_timestamp() {
+        assert_eq!(
+            parse_wayback_timestamp(
+                "https://web.archive.org/web/19941106084937/http://example.com/"
+            ),
+            Some(Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap())
+        );
+        assert_eq!(parse_wayback_timestamp("https://example.com/web/"), None);
+    }
+
+    fn url_strategy() -> impl Strategy<Value = String> {
+        "https?://[a-z]{1,10}\\.[a-z]{2,3}/[a-zA-Z0-9/?&=;,._~%-]{0,40}"
+    }
+
+    proptest! {
+        #[test]
+        fn refresh_roundtrip(
+            delay in 0u64..100_000,
+            url in url_strategy(),
+            key in "(?i)url",
+            quote in prop::sample::select(vec!["", "\"", "'"]),
+            sep in prop::sample::select(vec![";", ",", " ;", "; "]),
+        ) {
+            let value = format!("{}{}{}={}{}{}", delay, sep, key, quote, url, quote);
+            let refresh = parse_refresh(&value).unwrap();
+            prop_assert_eq!(refresh.delay, delay);
+            prop_assert_eq!(refresh.url, Some(url));
+        }
+
+        #[test]
+        fn http_date_roundtrip(secs in 0i64..4_102_444_800) {
+            let date = Utc.timestamp_opt(secs, 0).unwrap();
+            let imf = date.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            let asctime = date.format("%a %b %e %H:%M:%S %Y").to_string();
+            prop_assert_eq!(parse_http_date(&imf), Some(date));
+            prop_assert_eq!(parse_http_date(&asctime), Some(date));
+        }
+
+        #[test]
+        fn rfc850_roundtrip(secs in 0i64..3_155_760_000) {
+            // two digit years cover 1970-2069
+            let date = Utc.timestamp_opt(secs, 0).unwrap();
+            let rfc850 = date.format("%A, %d-%b-%y %H:%M:%S GMT").to_string();
+            prop_assert_eq!(parse_http_date(&rfc850), Some(date));
+        }
+
+        #[test]
+        fn parsers_never_panic(value in "\\PC*") {
+            let _ = parse_refresh(&value);
+            let _ = parse_http_date(&value);
+        }
+    }
+}
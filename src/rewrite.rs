@@ -0,0 +1,403 @@
+//! Rewriting the links of documents to point to their archives.
+//!
+//! ```
+//! use archiveis::{
+//!     extract::{DocumentFormat, Extractor},
+//!     rewrite::{rewrite, RewriteMode},
+//!     Archived, Provider,
+//! };
+//!
+//! let markdown = "See [the example](https://example.com/).";
+//! let links = Extractor::new().links(markdown, DocumentFormat::Markdown);
+//! let rewritten = rewrite(markdown, DocumentFormat::Markdown, &links, RewriteMode::Append, |url| {
+//!     vec![Archived {
+//!         target_url: url.to_string(),
+//!         archived_url: "http://archive.is/abcd".to_string(),
+//!         time_stamp: None,
+//!         submit_token: String::new(),
+//!         provider: Provider::ArchiveIs,
+//!     }]
+//! });
+//! assert_eq!(
+//!     rewritten,
+//!     "See [the example](https://example.com/) ([archived](http://archive.is/abcd))."
+//! );
+//! ```
+
+use crate::{
+    extract::{DocumentFormat, Link},
+    Archived,
+};
+use std::{fmt, ops::Range, str::FromStr};
+use url::Url;
+
+/// How `rewrite` adds the archives of links to a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RewriteMode {
+    /// Keeps the original links and adds a link to the archives next to each of them
+    #[default]
+    Append,
+    /// Replaces the original links with the link to their first archive
+    Replace,
+}
+
+impl fmt::Display for RewriteMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewriteMode::Append => write!(f, "append"),
+            RewriteMode::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+impl FromStr for RewriteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "append" => Ok(RewriteMode::Append),
+            "replace" => Ok(RewriteMode::Replace),
+            _ => Err(format!("Unknown rewrite mode {}", s)),
+        }
+    }
+}
+
+/// Rewrites the `links` of a document to point to their archives.
+///
+/// `archives` returns the archives of the url of a link, links without archives stay as they
+/// are. Everything else of the document is preserved.
+///
+/// Only links that a reader follows are rewritten, see `rewritable`, the stylesheets, scripts
+/// and images of a document are left as they are.
+///
+/// With `RewriteMode::Append` the archives are added after the whole link, like
+/// `[text](url) ([archived](archive))` in Markdown or `<a href="url">text</a> <a
+/// href="archive">(archived)</a>` in html. Links that can't be followed by another link, like
+/// Markdown reference definitions, are left as they are.
+pub fn rewrite<F>(
+    document: &str,
+    format: DocumentFormat,
+    links: &[Link],
+    mode: RewriteMode,
+    archives: F,
+) -> String
+where
+    F: Fn(&Url) -> Vec<Archived>,
+{
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for link in links {
+        if !is_rewritable(document, format, link) {
+            continue;
+        }
+        let archives = archives(&link.url);
+        let first = match archives.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        let format = link_format(document, format, link);
+        match mode {
+            RewriteMode::Replace => {
+                edits.push((link.range.clone(), escape(format, &first.archived_url)))
+            }
+            RewriteMode::Append => {
+                if let Some(end) = append_position(document, format, link) {
+                    edits.push((end..end, archive_links(format, &archives)));
+                }
+            }
+        }
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+
+    let mut rewritten = String::with_capacity(document.len());
+    let mut pos = 0;
+    for (range, text) in edits {
+        if range.start < pos {
+            continue;
+        }
+        rewritten.push_str(&document[pos..range.start]);
+        rewritten.push_str(&text);
+        pos = range.end;
+    }
+    rewritten.push_str(&document[pos..]);
+    rewritten
+}
+
+/// Keeps the links of a document that `rewrite` changes.
+///
+/// These are the `href`s of html anchors, and in Markdown inline links, reference definitions,
+/// autolinks and bare urls, but not images. The urls of stylesheets, scripts, images and other
+/// resources a document embeds are dropped, an archive is no replacement for them.
+pub fn rewritable(document: &str, format: DocumentFormat, links: Vec<Link>) -> Vec<Link> {
+    links
+        .into_iter()
+        .filter(|link| is_rewritable(document, format, link))
+        .collect()
+}
+
+/// Whether `link` is followed by readers of the document, rather than embedded into it
+fn is_rewritable(document: &str, format: DocumentFormat, link: &Link) -> bool {
+    let before = &document[..link.range.start];
+    match (open_tag(before), format) {
+        // html within Markdown too
+        (Some(tag), DocumentFormat::Html) | (Some(tag), DocumentFormat::Markdown) => {
+            is_anchor_href(tag)
+        }
+        (None, DocumentFormat::Html) => false,
+        (None, DocumentFormat::Markdown) => !is_markdown_image(before),
+        (_, DocumentFormat::Text) => true,
+    }
+}
+
+/// The format of the markup around `link`, html for the anchors within a Markdown document
+fn link_format(document: &str, format: DocumentFormat, link: &Link) -> DocumentFormat {
+    match format {
+        DocumentFormat::Markdown if open_tag(&document[..link.range.start]).is_some() => {
+            DocumentFormat::Html
+        }
+        format => format,
+    }
+}
+
+/// The part of the html tag that is still open at the end of `before`, like
+/// `<a class="x" href="`
+fn open_tag(before: &str) -> Option<&str> {
+    let tag = &before[before.rfind('<')?..];
+    let is_tag = tag[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        && tag.contains(|c: char| c.is_ascii_whitespace())
+        && !tag.contains('>');
+    if is_tag {
+        Some(tag)
+    } else {
+        None
+    }
+}
+
+/// Whether the open `tag` is an `<a>` tag that ends within the value of its `href`
+fn is_anchor_href(tag: &str) -> bool {
+    let name_end = tag
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(tag.len());
+    let attr = tag
+        .trim_end_matches(['"', '\''])
+        .trim_end()
+        .trim_end_matches('=')
+        .trim_end();
+    let attr = attr
+        .rsplit(|c: char| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+    tag[1..name_end].eq_ignore_ascii_case("a") && attr.eq_ignore_ascii_case("href")
+}
+
+/// Whether a Markdown link that starts after `before` is the source of an image `![alt](src)`
+fn is_markdown_image(before: &str) -> bool {
+    let opening = before.trim_end_matches('<').trim_end_matches(' ');
+    match opening.strip_suffix("](") {
+        Some(text) => text
+            .rfind('[')
+            .is_some_and(|open| text[..open].ends_with('!')),
+        None => false,
+    }
+}
+
+/// Escapes an url for the document
+fn escape(format: DocumentFormat, url: &str) -> String {
+    match format {
+        DocumentFormat::Html => url.replace('&', "&amp;").replace('"', "&quot;"),
+        DocumentFormat::Markdown => url
+            .replace(' ', "%20")
+            .replace('(', "%28")
+            .replace(')', "%29"),
+        DocumentFormat::Text => url.to_string(),
+    }
+}
+
+/// The links to the archives, labeled `archived` or with the providers if there are several
+fn archive_links(format: DocumentFormat, archives: &[Archived]) -> String {
+    let label = |archived: &Archived| {
+        if archives.len() == 1 {
+            "archived".to_string()
+        } else {
+            archived.provider.to_string()
+        }
+    };
+    let links: Vec<_> = archives
+        .iter()
+        .map(|archived| {
+            let url = escape(format, &archived.archived_url);
+            match format {
+                DocumentFormat::Html if archives.len() == 1 => {
+                    format!("<a href=\"{}\">({})</a>", url, label(archived))
+                }
+                DocumentFormat::Html => format!("<a href=\"{}\">{}</a>", url, label(archived)),
+                DocumentFormat::Markdown => format!("[{}]({})", label(archived), url),
+                DocumentFormat::Text => format!("{}: {}", label(archived), url),
+            }
+        })
+        .collect();
+    match format {
+        DocumentFormat::Html if archives.len() == 1 => format!(" {}", links[0]),
+        _ => format!(" ({})", links.join(", ")),
+    }
+}
+
+/// The position after the whole link, where links to its archives can be added
+fn append_position(document: &str, format: DocumentFormat, link: &Link) -> Option<usize> {
+    let before = &document[..link.range.start];
+    let after = &document[link.range.end..];
+    match format {
+        DocumentFormat::Html => {
+            // only the href of an `<a>` tag, after its end tag
+            let tag = &before[before.rfind('<')?..];
+            let name_end = tag
+                .find(|c: char| c.is_ascii_whitespace())
+                .unwrap_or(tag.len());
+            if !tag[1..name_end].eq_ignore_ascii_case("a") {
+                return None;
+            }
+            let end_tag = after
+                .as_bytes()
+                .windows(3)
+                .position(|window| window.eq_ignore_ascii_case(b"</a"))?;
+            let end = after[end_tag..].find('>')?;
+            Some(link.range.end + end_tag + end + 1)
+        }
+        DocumentFormat::Markdown => {
+            let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let prefix = document[line_start..link.range.start].trim_start();
+            let opening = before.trim_end_matches('<').trim_end_matches(' ');
+            if opening.ends_with("](") {
+                // an inline link, after the closing parenthesis behind the optional title
+                let mut quote = None;
+                for (i, c) in after.char_indices() {
+                    match (quote, c) {
+                        (Some(q), c) if q == c => quote = None,
+                        (Some(_), _) => {}
+                        (None, '"') | (None, '\'') => quote = Some(c),
+                        (None, ')') => return Some(link.range.end + i + 1),
+                        _ => {}
+                    }
+                }
+                None
+            } else if before.ends_with('<') && after.starts_with('>') {
+                // an autolink
+                Some(link.range.end + 1)
+            } else if prefix.starts_with('[') && prefix.contains("]:") {
+                // a reference definition
+                None
+            } else {
+                Some(link.range.end)
+            }
+        }
+        DocumentFormat::Text => Some(link.range.end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{extract::Extractor, Provider};
+
+    fn archives(url: &Url) -> Vec<Archived> {
+        if url.host_str() == Some("example.org") {
+            return Vec::new();
+        }
+        [Provider::ArchiveIs, Provider::Wayback]
+            .iter()
+            .take(if url.path() == "/both" { 2 } else { 1 })
+            .map(|provider| Archived {
+                target_url: url.to_string(),
+                archived_url: format!("https://{}/{}", provider, url.path().trim_matches('/')),
+                time_stamp: None,
+                submit_token: String::new(),
+                provider: provider.clone(),
+            })
+            .collect()
+    }
+
+    fn rewritten(document: &str, format: DocumentFormat, mode: RewriteMode) -> String {
+        let links = Extractor::new().links(document, format);
+        rewrite(document, format, &links, mode, archives)
+    }
+
+    #[test]
+    fn rewrite_markdown() {
+        let markdown = "* [a](https://example.com/a \"Title\") and <https://example.com/both>
+* https://example.com/c, [unarchived](https://example.org/)
+
+[ref]: https://example.com/ref
+";
+        assert_eq!(
+            rewritten(markdown, DocumentFormat::Markdown, RewriteMode::Append),
+            "* [a](https://example.com/a \"Title\") ([archived](https://archiveis/a)) and <https://example.com/both> ([archiveis](https://archiveis/both), [wayback](https://wayback/both))
+* https://example.com/c ([archived](https://archiveis/c)), [unarchived](https://example.org/)
+
+[ref]: https://example.com/ref
+"
+        );
+        assert_eq!(
+            rewritten(markdown, DocumentFormat::Markdown, RewriteMode::Replace),
+            "* [a](https://archiveis/a \"Title\") and <https://archiveis/both>
+* https://archiveis/c, [unarchived](https://example.org/)
+
+[ref]: https://archiveis/ref
+"
+        );
+    }
+
+    #[test]
+    fn rewrite_html() {
+        let html =
+            r#"<p><A HREF="https://example.com/a">a</A>, <img src="https://example.com/img"></p>"#;
+        assert_eq!(
+            rewritten(html, DocumentFormat::Html, RewriteMode::Append),
+            r#"<p><A HREF="https://example.com/a">a</A> <a href="https://archiveis/a">(archived)</a>, <img src="https://example.com/img"></p>"#
+        );
+        assert_eq!(
+            rewritten(html, DocumentFormat::Html, RewriteMode::Replace),
+            r#"<p><A HREF="https://archiveis/a">a</A>, <img src="https://example.com/img"></p>"#
+        );
+    }
+
+    #[test]
+    fn keep_embedded_resources() {
+        let html = r#"<head><link rel="stylesheet" href="https://example.com/style.css">
+<link rel="icon" href="https://example.com/favicon.ico">
+<script src="https://example.com/app.js"></script></head>
+<body><a class="x" href='https://example.com/a'>a</a></body>"#;
+        let links = Extractor::new().links(html, DocumentFormat::Html);
+        assert_eq!(links.len(), 4);
+        assert_eq!(rewritable(html, DocumentFormat::Html, links).len(), 1);
+        for mode in &[RewriteMode::Append, RewriteMode::Replace] {
+            let rewritten = rewritten(html, DocumentFormat::Html, *mode);
+            assert!(rewritten.contains(r#"href="https://example.com/style.css""#));
+            assert!(rewritten.contains(r#"href="https://example.com/favicon.ico""#));
+            assert!(rewritten.contains(r#"<script src="https://example.com/app.js"></script>"#));
+            assert!(rewritten.contains("https://archiveis/a"));
+        }
+
+        let markdown = "![logo](https://example.com/logo.png) [a](https://example.com/a)
+<img src=\"https://example.com/img.png\"> <a href=\"https://example.com/b\">b</a>
+";
+        assert_eq!(
+            rewritten(markdown, DocumentFormat::Markdown, RewriteMode::Replace),
+            "![logo](https://example.com/logo.png) [a](https://archiveis/a)
+<img src=\"https://example.com/img.png\"> <a href=\"https://archiveis/b\">b</a>
+"
+        );
+    }
+
+    #[test]
+    fn rewrite_html_in_markdown() {
+        let markdown =
+            "See <a href=\"https://example.com/x(1)&y\">x</a> and [b](https://example.com/b).\n";
+        assert_eq!(
+            rewritten(markdown, DocumentFormat::Markdown, RewriteMode::Append),
+            "See <a href=\"https://example.com/x(1)&y\">x</a> <a href=\"https://archiveis/x(1)&amp;y\">(archived)</a> and [b](https://example.com/b) ([archived](https://archiveis/b)).\n"
+        );
+        assert_eq!(
+            rewritten(markdown, DocumentFormat::Markdown, RewriteMode::Replace),
+            "See <a href=\"https://archiveis/x(1)&amp;y\">x</a> and [b](https://archiveis/b).\n"
+        );
+    }
+}
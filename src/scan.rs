@@ -0,0 +1,249 @@
+//! Finding the links in the files of a directory.
+//!
+//! The `Scanner` walks a directory like a source tree, skips the files ignored by `.gitignore`,
+//! `.ignore` and the global git excludes, and extracts the links of every text file with the
+//! rules for its format: html and Markdown files by their links, all other files by the bare
+//! urls in their text, like the urls in the comments of source code. It is only available with
+//! the `scan` feature enabled.
+//!
+//! ```no_run
+//! # use archiveis::scan::Scanner;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! for scanned in Scanner::new().scan("docs")? {
+//!     for location in &scanned.locations {
+//!         println!("{}:{} {}", location.path.display(), location.line, scanned.url);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    extract::{DocumentFormat, Extractor},
+    Error, Result,
+};
+use ignore::WalkBuilder;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// Extensions of files that are never scanned, because their urls are no links worth archiving,
+/// like the registry urls of lock files or the xml namespaces of svg images.
+const SKIPPED_EXTENSIONS: &[&str] = &["lock", "map", "svg"];
+
+/// Where a link was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The path of the file, relative to the scanned directory
+    pub path: PathBuf,
+    /// The line within the file, starting at 1
+    pub line: usize,
+}
+
+/// A link found in the scanned files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scanned {
+    /// The url of the link
+    pub url: Url,
+    /// Every place the link was found, in the order the files were scanned
+    pub locations: Vec<Location>,
+}
+
+/// Walks directories and extracts the links of their files
+#[derive(Debug, Clone, Default)]
+pub struct Scanner {
+    /// Extracts the links of a single file
+    extractor: Extractor,
+    /// Whether hidden files and directories are scanned as well
+    hidden: bool,
+}
+
+impl Scanner {
+    /// Creates a new `Scanner` that extracts every absolute link of the files that are not
+    /// hidden or ignored
+    pub fn new() -> Self {
+        Scanner::default()
+    }
+
+    /// Extracts the links of the files with `extractor`, to restrict them to certain domains
+    pub fn extractor(mut self, extractor: Extractor) -> Self {
+        self.extractor = extractor;
+        self
+    }
+
+    /// Whether hidden files and directories, like `.github`, are scanned as well
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// All distinct links in the files below `root`, in the order they were first found.
+    ///
+    /// The files are scanned sorted by their path. Binary files and files that are not valid
+    /// UTF-8 are skipped, as are directories and files that can't be read.
+    pub fn scan<P: AsRef<Path>>(&self, root: P) -> Result<Vec<Scanned>> {
+        let root = root.as_ref();
+        // fail early if the directory doesn't exist at all
+        fs::metadata(root)?;
+        let walk = WalkBuilder::new(root)
+            .hidden(!self.hidden)
+            // respect `.gitignore` files also outside of git repositories
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        let mut scanned: Vec<Scanned> = Vec::new();
+        let mut index: HashMap<Url, usize> = HashMap::new();
+        for entry in walk {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Skipping {}", err);
+                    continue;
+                }
+            };
+            if !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                continue;
+            }
+            let path = entry.path();
+            let skipped = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| SKIPPED_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if skipped {
+                continue;
+            }
+            let content = match fs::read(path).map_err(Error::from).and_then(|content| {
+                String::from_utf8(content).map_err(|err| Error::InvalidDocument(err.to_string()))
+            }) {
+                Ok(content) if !content.contains('\0') => content,
+                Ok(_) => continue,
+                Err(err) => {
+                    debug!("Skipping {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            let relative = if relative.as_os_str().is_empty() {
+                // `root` is a single file
+                path.file_name().map(Path::new).unwrap_or(path)
+            } else {
+                relative
+            };
+            let format = DocumentFormat::guess(Some(path), &content);
+            let lines = LineIndex::new(&content);
+            for link in self.extractor.links(&content, format) {
+                let location = Location {
+                    path: relative.to_path_buf(),
+                    line: lines.line(link.range.start),
+                };
+                match index.get(&link.url) {
+                    Some(&idx) => {
+                        // the same link repeated within a line is reported once
+                        if scanned[idx].locations.last() != Some(&location) {
+                            scanned[idx].locations.push(location);
+                        }
+                    }
+                    None => {
+                        index.insert(link.url.clone(), scanned.len());
+                        scanned.push(Scanned {
+                            url: link.url,
+                            locations: vec![location],
+                        });
+                    }
+                }
+            }
+        }
+        Ok(scanned)
+    }
+}
+
+/// Finds the line of a position within a text
+struct LineIndex {
+    /// The position of the start of every line
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(pos, _)| pos + 1))
+            .collect();
+        LineIndex { starts }
+    }
+
+    /// The line of `pos`, starting at 1
+    fn line(&self, pos: usize) -> usize {
+        match self.starts.binary_search(&pos) {
+            Ok(line) => line + 1,
+            Err(line) => line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_tree() {
+        let root = std::env::temp_dir().join(format!("archiveis-scan-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(
+            root.join("README.md"),
+            "# Title\n\nSee [the docs](https://example.com/docs).\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/main.rs"),
+            "// https://example.com/docs and https://example.com/docs\nfn main() {\n    \
+             let url = \"https://example.org/api\";\n}\n",
+        )
+        .unwrap();
+        fs::write(root.join("target/out.txt"), "https://example.com/ignored").unwrap();
+        fs::write(
+            root.join("image.svg"),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+        )
+        .unwrap();
+        fs::write(root.join("data.bin"), b"https://example.com/\0\xff").unwrap();
+
+        let scanned = Scanner::new().scan(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        let locations: Vec<_> = scanned
+            .iter()
+            .map(|scanned| {
+                let locations: Vec<_> = scanned
+                    .locations
+                    .iter()
+                    .map(|location| format!("{}:{}", location.path.display(), location.line))
+                    .collect();
+                (scanned.url.as_str(), locations)
+            })
+            .collect();
+        let main = Path::new("src").join("main.rs");
+        assert_eq!(
+            locations,
+            vec![
+                (
+                    "https://example.com/docs",
+                    vec!["README.md:3".to_string(), format!("{}:1", main.display())]
+                ),
+                (
+                    "https://example.org/api",
+                    vec![format!("{}:3", main.display())]
+                ),
+            ]
+        );
+        assert!(Scanner::new().scan(root.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Reading the pages of a site from its sitemap.
+//!
+//! Supports sitemaps and sitemap indexes as described at <https://www.sitemaps.org/protocol.html>,
+//! both plain and gzip compressed. It is only available with the `sitemap` feature enabled.
+//!
+//! ```no_run
+//! # use archiveis::sitemap::SitemapClient;
+//! # use futures::StreamExt;
+//! #
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = SitemapClient::default();
+//! let sitemap = client.fetch(&"https://example.com/sitemap.xml".parse()?).await?;
+//! let mut pages = Box::pin(client.pages(sitemap));
+//! while let Some(page) = pages.next().await {
+//!     println!("{}", page?.loc);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use crate::transport;
+use crate::{html, parse, Error, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+use futures::{stream, Future, Stream};
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use std::sync::Arc;
+use url::Url;
+
+/// An entry of a sitemap, either a page or another sitemap of an index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    /// The url of the page or sitemap
+    pub loc: Url,
+    /// When the page or sitemap was last modified
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+/// A parsed sitemap file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sitemap {
+    /// A `<urlset>` listing pages
+    Pages(Vec<SitemapEntry>),
+    /// A `<sitemapindex>` listing further sitemaps
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    /// Parses the xml of a sitemap or sitemap index.
+    ///
+    /// Entries without a valid `<loc>` are skipped. Only the elements of the sitemap protocol
+    /// are read, the `<image:loc>` or `<video:content_loc>` of extensions don't replace the `<loc>`
+    /// of an entry.
+    pub fn parse(xml: &str) -> Result<Self> {
+        let tags = html::tags(xml);
+        let root = tags
+            .iter()
+            .find(|tag| matches!(tag.local_name(), "urlset" | "sitemapindex"));
+        let root = match root {
+            Some(root) => root,
+            None => {
+                return Err(Error::InvalidDocument(
+                    "no <urlset> or <sitemapindex> found".to_string(),
+                ))
+            }
+        };
+        let index = root.local_name() == "sitemapindex";
+        let entry = if index { "sitemap" } else { "url" };
+        // the elements of the protocol have the namespace prefix of the root, if any, while
+        // extensions have their own
+        let prefix = &root.name[..root.name.len() - root.local_name().len()];
+        let mut entries = Vec::new();
+        let mut current: Option<(Option<Url>, Option<DateTime<Utc>>)> = None;
+        for tag in &tags {
+            let name = match tag.name.strip_prefix(prefix) {
+                Some(name) => name,
+                None => continue,
+            };
+            match name {
+                name if name == entry => {
+                    if let Some((Some(loc), lastmod)) = current.take() {
+                        entries.push(SitemapEntry { loc, lastmod });
+                    }
+                    current = Some((None, None));
+                }
+                "loc" => {
+                    if let Some(current) = &mut current {
+                        // the first valid location of the entry
+                        if current.0.is_none() {
+                            current.0 = tag.inner_text().parse().ok();
+                        }
+                    }
+                }
+                "lastmod" => {
+                    if let Some(current) = &mut current {
+                        current.1 = parse::parse_w3c_datetime(&tag.inner_text());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some((Some(loc), lastmod)) = current {
+            entries.push(SitemapEntry { loc, lastmod });
+        }
+        Ok(if index {
+            Sitemap::Index(entries)
+        } else {
+            Sitemap::Pages(entries)
+        })
+    }
+
+    /// Parses the content of a sitemap file, which may be gzip compressed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        // the magic number of gzip
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut xml = String::new();
+            GzDecoder::new(bytes).read_to_string(&mut xml)?;
+            Sitemap::parse(&xml)
+        } else {
+            Sitemap::parse(&String::from_utf8_lossy(bytes))
+        }
+    }
+}
+
+/// Fetches sitemaps and resolves sitemap indexes
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+pub struct SitemapClient {
+    /// The internal Http Client of the selected runtime.
+    client: transport::HttpClient,
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+impl SitemapClient {
+    /// Creates a new instance of the `SitemapClient` using a special user agent
+    pub fn new<T: ToString>(user_agent: T) -> Self {
+        SitemapClient {
+            client: transport::HttpClient::new(&user_agent.to_string()),
+        }
+    }
+
+    /// Fetches and parses the sitemap at `url`
+    pub async fn fetch(&self, url: &Url) -> Result<Sitemap> {
+        let resp = self.client.get(url.as_str()).await?;
+        if !resp.is_success() {
+            error!("Failed to fetch sitemap {}: status {}", url, resp.status);
+            return Err(Error::ServerError(url.to_string()));
+        }
+        Sitemap::from_bytes(&resp.body)
+    }
+
+    /// All pages of the sitemap.
+    ///
+    /// The sitemaps of an index are fetched one after another only when the pages of the previous
+    /// one were consumed, a sitemap that can't be fetched yields an error and the stream continues
+    /// with the next one.
+    pub fn pages(self, sitemap: Sitemap) -> impl Stream<Item = Result<SitemapEntry>> {
+        let client = Arc::new(self);
+        walk(sitemap, move |url| {
+            let client = Arc::clone(&client);
+            async move { client.fetch(&url).await }
+        })
+    }
+}
+
+/// All pages of `sitemap`, the sitemaps of indexes are fetched with `fetch` when they're needed.
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+fn walk<F, Fut>(sitemap: Sitemap, fetch: F) -> impl Stream<Item = Result<SitemapEntry>>
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = Result<Sitemap>>,
+{
+    let mut state = Pages {
+        pages: VecDeque::new(),
+        sitemaps: VecDeque::new(),
+        seen: HashSet::new(),
+    };
+    state.push(sitemap);
+    stream::unfold((state, fetch), |(mut state, fetch)| async move {
+        loop {
+            if let Some(page) = state.pages.pop_front() {
+                return Some((Ok(page), (state, fetch)));
+            }
+            let url = state.sitemaps.pop_front()?;
+            match fetch(url).await {
+                Ok(sitemap) => state.push(sitemap),
+                Err(err) => return Some((Err(err), (state, fetch))),
+            }
+        }
+    })
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+impl Default for SitemapClient {
+    fn default() -> Self {
+        SitemapClient::new("archiveis-rs")
+    }
+}
+
+/// The state of `walk`
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+struct Pages {
+    /// The pages not handed out yet
+    pages: VecDeque<SitemapEntry>,
+    /// The sitemaps not fetched yet
+    sitemaps: VecDeque<Url>,
+    /// All sitemaps of indexes so far, so that cyclic indexes end
+    seen: HashSet<Url>,
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std", test))]
+impl Pages {
+    fn push(&mut self, sitemap: Sitemap) {
+        match sitemap {
+            Sitemap::Pages(pages) => self.pages.extend(pages),
+            Sitemap::Index(sitemaps) => {
+                for sitemap in sitemaps {
+                    if self.seen.insert(sitemap.loc.clone()) {
+                        self.sitemaps.push_back(sitemap.loc);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use flate2::{write::GzEncoder, Compression};
+    use futures::{executor::block_on, StreamExt};
+    use std::{cell::RefCell, collections::HashMap, io::Write};
+
+    #[test]
+    fn parse_sitemaps() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>http://www.example.com/?a=1&amp;b=2</loc>
+    <lastmod>2005-01-01</lastmod>
+    <changefreq>monthly</changefreq>
+  </url>
+  <url><loc>http://www.example.com/catalog</loc></url>
+  <url><loc>not an url</loc></url>
+</urlset>"#;
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(xml.as_bytes()).unwrap();
+        let sitemap = Sitemap::from_bytes(&gzip.finish().unwrap()).unwrap();
+        assert_eq!(
+            sitemap,
+            Sitemap::Pages(vec![
+                SitemapEntry {
+                    loc: "http://www.example.com/?a=1&b=2".parse().unwrap(),
+                    lastmod: Some(Utc.with_ymd_and_hms(2005, 1, 1, 0, 0, 0).unwrap()),
+                },
+                SitemapEntry {
+                    loc: "http://www.example.com/catalog".parse().unwrap(),
+                    lastmod: None,
+                },
+            ])
+        );
+
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+   <sitemap><loc>http://www.example.com/sitemap1.xml.gz</loc></sitemap>
+</sitemapindex>"#;
+        match Sitemap::from_bytes(index.as_bytes()).unwrap() {
+            Sitemap::Index(sitemaps) => assert_eq!(
+                sitemaps[0].loc.as_str(),
+                "http://www.example.com/sitemap1.xml.gz"
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Sitemap::parse("<html></html>").is_err());
+    }
+    #[test]
+    fn extension_locations() {
+        let xml = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1"
+        xmlns:video="http://www.google.com/schemas/sitemap-video/1.1">
+  <url>
+    <image:image><image:loc>https://example.com/before.jpg</image:loc></image:image>
+    <loc>https://example.com/page</loc>
+    <image:image><image:loc>https://example.com/after.jpg</image:loc></image:image>
+    <video:video><video:content_loc>https://example.com/video.mp4</video:content_loc></video:video>
+  </url>
+</urlset>"#;
+        match Sitemap::parse(xml).unwrap() {
+            Sitemap::Pages(pages) => {
+                assert_eq!(pages.len(), 1);
+                assert_eq!(pages[0].loc.as_str(), "https://example.com/page");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let prefixed = r#"<sm:urlset xmlns:sm="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sm:url><sm:loc>https://example.com/a</sm:loc><image:loc>https://example.com/a.jpg</image:loc></sm:url>
+</sm:urlset>"#;
+        match Sitemap::parse(prefixed).unwrap() {
+            Sitemap::Pages(pages) => assert_eq!(pages[0].loc.as_str(), "https://example.com/a"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn walk_indexes() {
+        let index = |sitemaps: &[&str]| {
+            let entries: String = sitemaps
+                .iter()
+                .map(|name| format!("<sitemap><loc>http://example.com/{}</loc></sitemap>", name))
+                .collect();
+            format!("<sitemapindex>{}</sitemapindex>", entries)
+        };
+        let pages = |pages: &[&str]| {
+            let entries: String = pages
+                .iter()
+                .map(|name| format!("<url><loc>http://example.com/{}</loc></url>", name))
+                .collect();
+            format!("<urlset>{}</urlset>", entries)
+        };
+        // a.xml and b.xml list each other
+        let mut files = HashMap::new();
+        files.insert("/b.xml", index(&["a.xml", "pages2.xml", "missing.xml"]));
+        files.insert("/a.xml", index(&["b.xml", "pages1.xml"]));
+        files.insert("/pages1.xml", pages(&["1", "2"]));
+        files.insert("/pages2.xml", pages(&["3", "b.xml"]));
+        let fetched = RefCell::new(Vec::new());
+        let fetch = |url: Url| {
+            fetched.borrow_mut().push(url.path().to_string());
+            let xml = files.get(url.path()).cloned();
+            async move {
+                match xml {
+                    Some(xml) => Sitemap::parse(&xml),
+                    None => Err(Error::ServerError(url.to_string())),
+                }
+            }
+        };
+
+        let root = Sitemap::parse(&files["/a.xml"]).unwrap();
+        let results: Vec<_> = block_on(walk(root, fetch).collect());
+        let pages: Vec<_> = results
+            .iter()
+            .filter_map(|page| page.as_ref().ok())
+            .map(|page| page.loc.path())
+            .collect();
+        // a page that is named like a sitemap is still a page
+        assert_eq!(pages, vec!["/1", "/2", "/3", "/b.xml"]);
+        match results.last() {
+            Some(Err(Error::ServerError(url))) => assert_eq!(url, "http://example.com/missing.xml"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            fetched.into_inner(),
+            vec![
+                "/b.xml",
+                "/pages1.xml",
+                "/a.xml",
+                "/pages2.xml",
+                "/missing.xml"
+            ]
+        );
+    }
+}
@@ -0,0 +1,570 @@
+//! Persistent storage of capture results.
+//!
+//! A `CaptureStore` records the outcome of every capture, successful or not, so that the history
+//! of a url can be queried later. With the `sqlite` feature enabled, `SqliteStore` keeps the
+//! records in a SQLite database.
+
+#[cfg(feature = "with-serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Archived, Error, Provider, Result};
+use chrono::{DateTime, Utc};
+use std::{fmt, str::FromStr};
+
+/// The outcome of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "with-serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Status {
+    /// The url was archived successfully
+    Archived,
+    /// Capturing the url failed
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Archived => write!(f, "archived"),
+            Status::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "archived" => Ok(Status::Archived),
+            "failed" => Ok(Status::Failed),
+            _ => Err(format!("Unknown status {}", s)),
+        }
+    }
+}
+
+/// A single recorded capture
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Record {
+    /// The requested url
+    pub target_url: String,
+    /// The url of the snapshot, if the capture was successful
+    pub archived_url: Option<String>,
+    /// When the url was archived, or when the capture failed
+    pub time_stamp: DateTime<Utc>,
+    /// The submit token the capture was authorized with, if any
+    pub submit_token: Option<String>,
+    /// The service the url was captured with
+    pub provider: Provider,
+    /// Whether the capture was successful
+    pub status: Status,
+    /// Why the capture failed
+    pub error: Option<String>,
+}
+
+impl Record {
+    /// A record of a successful capture
+    pub fn archived(archived: &Archived) -> Self {
+        Record {
+            target_url: archived.target_url.clone(),
+            archived_url: Some(archived.archived_url.clone()),
+            time_stamp: archived.time_stamp.unwrap_or_else(Utc::now),
+            submit_token: Some(archived.submit_token.clone()).filter(|token| !token.is_empty()),
+            provider: archived.provider.clone(),
+            status: Status::Archived,
+            error: None,
+        }
+    }
+
+    /// A record of a failed capture
+    pub fn failed<T: ToString>(target_url: T, provider: Provider, error: &Error) -> Self {
+        Record {
+            target_url: target_url.to_string(),
+            archived_url: None,
+            time_stamp: Utc::now(),
+            submit_token: None,
+            provider,
+            status: Status::Failed,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// A record of the result of a capture
+    pub fn from_result<T: ToString>(
+        target_url: T,
+        provider: Provider,
+        result: &Result<Archived>,
+    ) -> Self {
+        match result {
+            Ok(archived) => Record::archived(archived),
+            Err(err) => Record::failed(target_url, provider, err),
+        }
+    }
+
+    /// The `Archived` of a successful capture
+    pub fn to_archived(&self) -> Option<Archived> {
+        Some(Archived {
+            target_url: self.target_url.clone(),
+            archived_url: self.archived_url.clone()?,
+            time_stamp: Some(self.time_stamp),
+            submit_token: self.submit_token.clone().unwrap_or_default(),
+            provider: self.provider.clone(),
+        })
+    }
+}
+
+/// Stores capture records and makes them queryable
+pub trait CaptureStore {
+    /// Records a capture
+    fn insert(&self, record: &Record) -> Result<()>;
+
+    /// All records of the url, oldest first
+    fn by_url(&self, target_url: &str) -> Result<Vec<Record>>;
+
+    /// All records with a time stamp in `from..to`, oldest first
+    fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Record>>;
+
+    /// The most recent successful capture of the url
+    fn latest(&self, target_url: &str) -> Result<Option<Record>> {
+        Ok(self
+            .by_url(target_url)?
+            .into_iter()
+            .rev()
+            .find(|record| record.status == Status::Archived))
+    }
+}
+
+/// An entry of a feed that was captured before
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeenEntry {
+    /// The url or path of the feed
+    pub feed_url: String,
+    /// The id of the entry within the feed
+    pub entry_id: String,
+    /// When the entry was last updated according to the feed, when it was seen
+    pub updated: Option<DateTime<Utc>>,
+    /// When the entry was seen
+    pub time_stamp: DateTime<Utc>,
+}
+
+/// Remembers the entries of feeds that were captured, so that later runs only capture new ones
+pub trait FeedStore {
+    /// Remembers an entry, replacing a previous one with the same feed and id
+    fn mark_seen(&self, entry: &SeenEntry) -> Result<()>;
+
+    /// The entry of the feed with the id, if it was seen before
+    fn seen(&self, feed_url: &str, entry_id: &str) -> Result<Option<SeenEntry>>;
+}
+
+#[cfg(feature = "with-serde")]
+pub use self::journal::Journal;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStore;
+
+#[cfg(feature = "with-serde")]
+mod journal {
+    use super::{CaptureStore, Record};
+    use crate::{Error, Result};
+    use chrono::{DateTime, Utc};
+    use std::{
+        fs::{self, File, OpenOptions},
+        io::{BufRead, BufReader, Write},
+        path::Path,
+        sync::Mutex,
+    };
+
+    /// A `CaptureStore` that appends every record as a line of json to a file.
+    ///
+    /// Every record is written and flushed as soon as it is inserted, so the journal of a bulk
+    /// run that gets interrupted still holds every capture that completed, which allows to resume
+    /// the run later.
+    pub struct Journal {
+        inner: Mutex<Inner>,
+    }
+
+    struct Inner {
+        file: File,
+        records: Vec<Record>,
+    }
+
+    impl Journal {
+        /// Creates a new empty journal at `path`, truncating an existing one
+        pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+            Ok(Journal {
+                inner: Mutex::new(Inner {
+                    file: File::create(path)?,
+                    records: Vec::new(),
+                }),
+            })
+        }
+
+        /// Opens the journal at `path` and reads all its records, creating it if it doesn't
+        /// exist yet
+        ///
+        /// A malformed line, like the last line of a journal whose process was killed while
+        /// writing, is skipped.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+            let path = path.as_ref();
+            let mut records = Vec::new();
+            if path.exists() {
+                for (idx, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(&line) {
+                        Ok(record) => records.push(record),
+                        Err(err) => warn!(
+                            "Skipping malformed line {} of journal {}: {}",
+                            idx + 1,
+                            path.display(),
+                            err
+                        ),
+                    }
+                }
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            // make sure the next record starts on a new line
+            if fs::read(path)?.last().is_some_and(|&last| last != b'\n') {
+                writeln!(file)?;
+            }
+            Ok(Journal {
+                inner: Mutex::new(Inner { file, records }),
+            })
+        }
+
+        /// All records of the journal in the order they were inserted
+        pub fn records(&self) -> Vec<Record> {
+            self.inner.lock().unwrap().records.clone()
+        }
+    }
+
+    impl CaptureStore for Journal {
+        fn insert(&self, record: &Record) -> Result<()> {
+            let line =
+                serde_json::to_string(record).map_err(|err| Error::Store(err.to_string()))?;
+            let mut inner = self.inner.lock().unwrap();
+            writeln!(inner.file, "{}", line)?;
+            inner.file.flush()?;
+            inner.records.push(record.clone());
+            Ok(())
+        }
+
+        fn by_url(&self, target_url: &str) -> Result<Vec<Record>> {
+            Ok(self
+                .inner
+                .lock()
+                .unwrap()
+                .records
+                .iter()
+                .filter(|record| record.target_url == target_url)
+                .cloned()
+                .collect())
+        }
+
+        fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Record>> {
+            let mut records: Vec<_> = self
+                .inner
+                .lock()
+                .unwrap()
+                .records
+                .iter()
+                .filter(|record| record.time_stamp >= from && record.time_stamp < to)
+                .cloned()
+                .collect();
+            records.sort_by_key(|record| record.time_stamp);
+            Ok(records)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{Archived, Provider};
+
+        #[test]
+        fn reopen_journal() {
+            let path =
+                std::env::temp_dir().join(format!("archiveis-journal-{}", std::process::id()));
+            let archived = Archived {
+                target_url: "http://example.com/".to_string(),
+                archived_url: "http://archive.is/abcd".to_string(),
+                time_stamp: None,
+                submit_token: String::new(),
+                provider: Provider::ArchiveIs,
+            };
+            let record = Record::archived(&archived);
+            {
+                let journal = Journal::create(&path).unwrap();
+                journal.insert(&record).unwrap();
+            }
+            // a line that was cut off when the process died
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            write!(file, "{{\"target_url\": \"http://exa").unwrap();
+
+            let journal = Journal::open(&path).unwrap();
+            assert_eq!(journal.records(), vec![record.clone()]);
+            journal.insert(&record).unwrap();
+            assert_eq!(Journal::open(&path).unwrap().records().len(), 2);
+            assert_eq!(journal.latest("http://example.com/").unwrap(), Some(record));
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{CaptureStore, FeedStore, Record, SeenEntry};
+    use crate::{Error, Result};
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use rusqlite::{params, Connection, OptionalExtension, Row};
+    use std::path::Path;
+
+    /// A `CaptureStore` backed by a SQLite database
+    ///
+    /// ```no_run
+    /// # use archiveis::store::{CaptureStore, SqliteStore};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = SqliteStore::open("captures.sqlite")?;
+    /// for record in store.by_url("http://example.com/")? {
+    ///     println!("{} {:?} {}", record.time_stamp, record.archived_url, record.status);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub struct SqliteStore {
+        conn: Connection,
+    }
+
+    impl SqliteStore {
+        /// Opens the database at `path`, creating it if it doesn't exist yet
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+            Self::init(Connection::open(path)?)
+        }
+
+        /// Opens a new database in memory
+        pub fn open_in_memory() -> Result<Self> {
+            Self::init(Connection::open_in_memory()?)
+        }
+
+        fn init(conn: Connection) -> Result<Self> {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS captures (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    target_url TEXT NOT NULL,
+                    archived_url TEXT,
+                    time_stamp TEXT NOT NULL,
+                    submit_token TEXT,
+                    provider TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    error TEXT
+                );
+                CREATE INDEX IF NOT EXISTS captures_target_url ON captures (target_url);
+                CREATE INDEX IF NOT EXISTS captures_time_stamp ON captures (time_stamp);
+                CREATE TABLE IF NOT EXISTS seen_entries (
+                    feed_url TEXT NOT NULL,
+                    entry_id TEXT NOT NULL,
+                    updated TEXT,
+                    time_stamp TEXT NOT NULL,
+                    PRIMARY KEY (feed_url, entry_id)
+                );",
+            )?;
+            Ok(SqliteStore { conn })
+        }
+
+        fn query<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<Record>> {
+            let mut stmt = self.conn.prepare(sql)?;
+            let rows = stmt.query_map(params, record_from_row)?;
+            let mut records = Vec::new();
+            for row in rows {
+                records.push(row??);
+            }
+            Ok(records)
+        }
+    }
+
+    const COLUMNS: &str =
+        "target_url, archived_url, time_stamp, submit_token, provider, status, error";
+
+    /// Time stamps are stored as RFC 3339 strings in UTC, which sort chronologically
+    fn time_stamp(date: &DateTime<Utc>) -> String {
+        date.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    fn parse_time_stamp(time_stamp: &str) -> Result<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(time_stamp)
+            .map(|time_stamp| time_stamp.with_timezone(&Utc))
+            .map_err(|err| Error::Store(format!("Invalid time stamp {}: {}", time_stamp, err)))
+    }
+
+    fn record_from_row(row: &Row) -> rusqlite::Result<Result<Record>> {
+        let time_stamp: String = row.get(2)?;
+        let provider: String = row.get(4)?;
+        let status: String = row.get(5)?;
+        let record = parse_time_stamp(&time_stamp).and_then(|time_stamp| {
+            Ok(Record {
+                target_url: row.get(0)?,
+                archived_url: row.get(1)?,
+                time_stamp,
+                submit_token: row.get(3)?,
+                provider: provider.into(),
+                status: status.parse().map_err(Error::Store)?,
+                error: row.get(6)?,
+            })
+        });
+        Ok(record)
+    }
+
+    impl CaptureStore for SqliteStore {
+        fn insert(&self, record: &Record) -> Result<()> {
+            self.conn.execute(
+                &format!(
+                    "INSERT INTO captures ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    COLUMNS
+                ),
+                params![
+                    record.target_url,
+                    record.archived_url,
+                    time_stamp(&record.time_stamp),
+                    record.submit_token,
+                    record.provider.to_string(),
+                    record.status.to_string(),
+                    record.error,
+                ],
+            )?;
+            Ok(())
+        }
+
+        fn by_url(&self, target_url: &str) -> Result<Vec<Record>> {
+            self.query(
+                &format!(
+                    "SELECT {} FROM captures WHERE target_url = ?1 ORDER BY time_stamp, id",
+                    COLUMNS
+                ),
+                params![target_url],
+            )
+        }
+
+        fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Record>> {
+            self.query(
+                &format!(
+                    "SELECT {} FROM captures WHERE time_stamp >= ?1 AND time_stamp < ?2 \
+                     ORDER BY time_stamp, id",
+                    COLUMNS
+                ),
+                params![time_stamp(&from), time_stamp(&to)],
+            )
+        }
+    }
+
+    impl FeedStore for SqliteStore {
+        fn mark_seen(&self, entry: &SeenEntry) -> Result<()> {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO seen_entries (feed_url, entry_id, updated, time_stamp) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    entry.feed_url,
+                    entry.entry_id,
+                    entry.updated.as_ref().map(time_stamp),
+                    time_stamp(&entry.time_stamp),
+                ],
+            )?;
+            Ok(())
+        }
+
+        fn seen(&self, feed_url: &str, entry_id: &str) -> Result<Option<SeenEntry>> {
+            let row = self
+                .conn
+                .query_row(
+                    "SELECT updated, time_stamp FROM seen_entries \
+                     WHERE feed_url = ?1 AND entry_id = ?2",
+                    params![feed_url, entry_id],
+                    |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            match row {
+                Some((updated, seen)) => Ok(Some(SeenEntry {
+                    feed_url: feed_url.to_string(),
+                    entry_id: entry_id.to_string(),
+                    updated: updated.as_deref().map(parse_time_stamp).transpose()?,
+                    time_stamp: parse_time_stamp(&seen)?,
+                })),
+                None => Ok(None),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{Archived, Provider};
+        use chrono::TimeZone;
+
+        #[test]
+        fn insert_and_query() {
+            let store = SqliteStore::open_in_memory().unwrap();
+            let archived = Archived {
+                target_url: "http://example.com/".to_string(),
+                archived_url: "http://archive.is/abcd".to_string(),
+                time_stamp: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+                submit_token: "token".to_string(),
+                provider: Provider::ArchiveIs,
+            };
+            store.insert(&Record::archived(&archived)).unwrap();
+            let mut failed = Record::failed(
+                "http://example.com/",
+                Provider::Wayback,
+                &Error::MissingUrl("http://example.com/".to_string()),
+            );
+            failed.time_stamp = Utc.with_ymd_and_hms(2020, 3, 1, 0, 0, 0).unwrap();
+            store.insert(&failed).unwrap();
+
+            let records = store.by_url("http://example.com/").unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0], Record::archived(&archived));
+            assert_eq!(records[1], failed);
+            assert_eq!(
+                store.latest("http://example.com/").unwrap(),
+                Some(Record::archived(&archived))
+            );
+
+            let january = store
+                .between(
+                    Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap(),
+                    Utc.with_ymd_and_hms(2020, 2, 1, 0, 0, 0).unwrap(),
+                )
+                .unwrap();
+            assert_eq!(january, vec![Record::archived(&archived)]);
+            assert!(store.by_url("http://example.org/").unwrap().is_empty());
+        }
+
+        #[test]
+        fn seen_entries() {
+            let store = SqliteStore::open_in_memory().unwrap();
+            let mut entry = SeenEntry {
+                feed_url: "https://example.com/atom.xml".to_string(),
+                entry_id: "tag:example.com,2024:1".to_string(),
+                updated: None,
+                time_stamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            };
+            assert_eq!(store.seen(&entry.feed_url, &entry.entry_id).unwrap(), None);
+            store.mark_seen(&entry).unwrap();
+            entry.updated = Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap());
+            store.mark_seen(&entry).unwrap();
+            assert_eq!(
+                store.seen(&entry.feed_url, &entry.entry_id).unwrap(),
+                Some(entry.clone())
+            );
+            assert_eq!(
+                store
+                    .seen("https://example.org/rss", &entry.entry_id)
+                    .unwrap(),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Formatting archived links with custom line templates.
+//!
+//! A template is text with placeholders in braces, like `[{target}]({archive})`, that are
+//! replaced with the fields of an archived link. Literal braces are written as `{{` and `}}`.
+//! The values can be escaped for the document the lines end up in, the text of the template
+//! itself is never escaped.
+//!
+//! ```
+//! use archiveis::{template::{Escape, Template}, Archived, Provider};
+//!
+//! let template: Template = "- [{target}]({archive})".parse().unwrap();
+//! let archived = Archived {
+//!     target_url: "https://example.com/a_b".to_string(),
+//!     archived_url: "http://archive.is/abcd".to_string(),
+//!     time_stamp: None,
+//!     submit_token: String::new(),
+//!     provider: Provider::ArchiveIs,
+//! };
+//! assert_eq!(
+//!     template.escape(Escape::Markdown).render_archived(&archived),
+//!     r"- [https://example.com/a\_b](http://archive.is/abcd)"
+//! );
+//! ```
+
+use crate::Archived;
+use chrono::SecondsFormat;
+use std::{fmt, str::FromStr};
+
+/// How the values of the placeholders are escaped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Escape {
+    /// The values are inserted as they are
+    #[default]
+    None,
+    /// Markdown punctuation is escaped with a backslash
+    Markdown,
+    /// `&`, `<`, `>` and quotes are replaced with html entities
+    Html,
+    /// Every value is quoted for POSIX shells, so that it is a single word
+    Shell,
+}
+
+impl Escape {
+    /// Escapes `value`
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Escape::None => value.to_string(),
+            Escape::Markdown => {
+                let mut escaped = String::with_capacity(value.len());
+                for c in value.chars() {
+                    if "\\`*_{}[]()<>#|!".contains(c) {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+                escaped
+            }
+            Escape::Html => value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+                .replace('\'', "&#39;"),
+            Escape::Shell => format!("'{}'", value.replace('\'', r"'\''")),
+        }
+    }
+}
+
+impl fmt::Display for Escape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Escape::None => write!(f, "none"),
+            Escape::Markdown => write!(f, "markdown"),
+            Escape::Html => write!(f, "html"),
+            Escape::Shell => write!(f, "shell"),
+        }
+    }
+}
+
+impl FromStr for Escape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Escape::None),
+            "markdown" | "md" => Ok(Escape::Markdown),
+            "html" => Ok(Escape::Html),
+            "shell" | "sh" => Ok(Escape::Shell),
+            _ => Err(format!("Unknown escaping {}", s)),
+        }
+    }
+}
+
+/// A piece of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// A line template with `{field}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+    escape: Escape,
+}
+
+impl Template {
+    /// The fields of an `Archived` that `render_archived` fills in
+    pub const ARCHIVED_FIELDS: &'static [&'static str] =
+        &["target", "archive", "provider", "timestamp", "token"];
+
+    /// Escapes the values of the placeholders with `escape`
+    pub fn escape(mut self, escape: Escape) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// The names of all placeholders, in the order they appear
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Field(name) => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// Fills in every placeholder with the escaped value of its field, unknown fields are
+    /// left empty
+    pub fn render<F: Fn(&str) -> Option<String>>(&self, value: F) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Field(name) => {
+                    line.push_str(&self.escape.apply(&value(name).unwrap_or_default()))
+                }
+            }
+        }
+        line
+    }
+
+    /// Fills in the fields of `archived`, see `ARCHIVED_FIELDS`
+    pub fn render_archived(&self, archived: &Archived) -> String {
+        self.render(|field| archived_field(archived, field))
+    }
+}
+
+/// The value of `field` of `archived`, if it is one of `Template::ARCHIVED_FIELDS`
+pub fn archived_field(archived: &Archived, field: &str) -> Option<String> {
+    match field {
+        "target" => Some(archived.target_url.clone()),
+        "archive" => Some(archived.archived_url.clone()),
+        "provider" => Some(archived.provider.to_string()),
+        "timestamp" => archived
+            .time_stamp
+            .map(|time_stamp| time_stamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        "token" => Some(archived.submit_token.clone()),
+        _ => None,
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed placeholder {{{}", name)),
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err("Empty placeholder {}".to_string());
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(name.to_string()));
+                }
+                '}' => return Err("Unmatched } in the template, use }} for a brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template {
+            parts,
+            escape: Escape::None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_templates() {
+        let template: Template = "{{{ target }}} -> {archive} ({timestamp}){title}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            template.fields().collect::<Vec<_>>(),
+            vec!["target", "archive", "timestamp", "title"]
+        );
+        let archived = Archived {
+            target_url: "https://example.com/?a=1&b='2'".to_string(),
+            archived_url: "http://archive.is/x".to_string(),
+            time_stamp: "2024-01-01T00:00:00Z".parse().ok(),
+            submit_token: String::new(),
+            provider: crate::Provider::ArchiveIs,
+        };
+        assert_eq!(
+            template.render_archived(&archived),
+            "{https://example.com/?a=1&b='2'} -> http://archive.is/x (2024-01-01T00:00:00Z)"
+        );
+        let template: Template = "<a href=\"{archive}\">{target}</a>".parse().unwrap();
+        assert_eq!(
+            template.escape(Escape::Html).render_archived(&archived),
+            "<a href=\"http://archive.is/x\">https://example.com/?a=1&amp;b=&#39;2&#39;</a>"
+        );
+        let template: Template = "curl {target}".parse().unwrap();
+        assert_eq!(
+            template.escape(Escape::Shell).render_archived(&archived),
+            r"curl 'https://example.com/?a=1&b='\''2'\'''"
+        );
+        assert_eq!(Escape::Markdown.apply("a_b [c]"), r"a\_b \[c\]");
+
+        assert!("{target".parse::<Template>().is_err());
+        assert!("{}".parse::<Template>().is_err());
+        assert!("a } b".parse::<Template>().is_err());
+    }
+}
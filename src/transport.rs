@@ -0,0 +1,217 @@
+//! The HTTP transport the clients are built on.
+//!
+//! The async transport is selected by the `runtime-tokio` (reqwest) and `runtime-async-std`
+//! (surf) features, if both are enabled `runtime-tokio` takes precedence. The blocking transport
+//! is always backed by reqwest's blocking client.
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-std"))]
+use crate::Result;
+
+/// A fully read HTTP response.
+#[derive(Debug, Clone)]
+pub(crate) struct Response {
+    /// The status code of the response
+    pub status: u16,
+    /// All headers of the response, names are lowercase
+    pub headers: Vec<(String, String)>,
+    /// The raw response body
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the value of the first header with the given name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the response has a 2xx status code.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Whether the service refused the request because of too many requests, either with a
+    /// `429 Too Many Requests` status or a page that asks to solve a CAPTCHA.
+    ///
+    /// Archived pages can embed CAPTCHAs of their own, so this is only meaningful for a response
+    /// in which no archive url was found.
+    pub fn is_rate_limited(&self) -> bool {
+        if self.status == 429 {
+            return true;
+        }
+        let html = self.text();
+        ["g-recaptcha", "h-captcha", "cf-challenge"]
+            .iter()
+            .any(|marker| html.contains(marker))
+    }
+
+    /// The body of the response as text.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "blocking"))]
+fn reqwest_headers(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(key, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (key.as_str().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The async transport backed by reqwest on the tokio runtime.
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl HttpClient {
+    pub fn new(user_agent: &str) -> Self {
+        let client = reqwest::ClientBuilder::default()
+            .user_agent(user_agent)
+            .build()
+            .expect("Failed to create reqwest client");
+        HttpClient { client }
+    }
+
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        Self::read(self.client.get(url).send().await?).await
+    }
+
+    pub async fn post_form(&self, url: &str, body: String) -> Result<Response> {
+        let resp = self
+            .client
+            .post(url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(body)
+            .send()
+            .await?;
+        Self::read(resp).await
+    }
+
+    async fn read(resp: reqwest::Response) -> Result<Response> {
+        let status = resp.status().as_u16();
+        let headers = reqwest_headers(resp.headers());
+        let body = resp.bytes().await?.to_vec();
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// The async transport backed by surf on the async-std runtime.
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+#[derive(Debug, Clone)]
+pub(crate) struct HttpClient {
+    client: surf::Client,
+    user_agent: String,
+}
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+impl HttpClient {
+    pub fn new(user_agent: &str) -> Self {
+        let client = surf::Client::new().with(surf::middleware::Redirect::default());
+        HttpClient {
+            client,
+            user_agent: user_agent.to_string(),
+        }
+    }
+
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let resp = self
+            .client
+            .get(url)
+            .header("User-Agent", self.user_agent.as_str())
+            .await?;
+        Self::read(resp).await
+    }
+
+    pub async fn post_form(&self, url: &str, body: String) -> Result<Response> {
+        let resp = self
+            .client
+            .post(url)
+            .header("User-Agent", self.user_agent.as_str())
+            .content_type("application/x-www-form-urlencoded")
+            .body(body)
+            .await?;
+        Self::read(resp).await
+    }
+
+    async fn read(mut resp: surf::Response) -> Result<Response> {
+        let status = resp.status() as u16;
+        let headers = resp
+            .iter()
+            .map(|(key, values)| (key.as_str().to_lowercase(), values.last().to_string()))
+            .collect();
+        let body = resp.body_bytes().await?;
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// The blocking transport backed by reqwest's blocking client.
+#[cfg(feature = "blocking")]
+pub(crate) mod blocking {
+    use super::{reqwest_headers, Response};
+    use crate::Result;
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct HttpClient {
+        client: reqwest::blocking::Client,
+    }
+
+    impl HttpClient {
+        pub fn new(user_agent: &str) -> Self {
+            let client = reqwest::blocking::ClientBuilder::default()
+                .user_agent(user_agent)
+                .build()
+                .expect("Failed to create reqwest client");
+            HttpClient { client }
+        }
+
+        pub fn get(&self, url: &str) -> Result<Response> {
+            Self::read(self.client.get(url).send()?)
+        }
+
+        pub fn post_form(&self, url: &str, body: String) -> Result<Response> {
+            let resp = self
+                .client
+                .post(url)
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(body)
+                .send()?;
+            Self::read(resp)
+        }
+
+        fn read(resp: reqwest::blocking::Response) -> Result<Response> {
+            let status = resp.status().as_u16();
+            let headers = reqwest_headers(resp.headers());
+            let body = resp.bytes()?.to_vec();
+            Ok(Response {
+                status,
+                headers,
+                body,
+            })
+        }
+    }
+}
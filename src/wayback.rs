@@ -0,0 +1,173 @@
+//! A client for the Save Page Now API of the Internet Archive's Wayback Machine.
+
+use crate::{
+    archived_from_timemap, parse, transport, Archived, Archiver, Error, IntoUrl, Provider, Result,
+};
+use async_trait::async_trait;
+use chrono::{SubsecRound, Utc};
+use url::Url;
+
+/// The base url of the Wayback Machine
+const WAYBACK_URL: &str = "https://web.archive.org";
+
+/// A Client that serves as a wrapper around the Wayback Machine's Save Page Now service
+pub struct WaybackClient {
+    /// The internal Http Client of the selected runtime.
+    client: transport::HttpClient,
+}
+
+impl WaybackClient {
+    /// Creates a new instance of the `WaybackClient` using a special user agent
+    pub fn new<T: ToString>(user_agent: T) -> Self {
+        WaybackClient {
+            client: transport::HttpClient::new(&user_agent.to_string()),
+        }
+    }
+
+    /// Invokes the Save Page Now service.
+    ///
+    /// Sends a GET request to `https://web.archive.org/save/<url>`. The snapshot's location is
+    /// read from the `Content-Location` or `Location` header of the response. If neither is
+    /// present, the most recent snapshot is looked up in the timemap of the url instead, and only
+    /// accepted if it was taken after the request was sent, so that an older snapshot is never
+    /// returned as a fresh capture.
+    /// The time stamp of the snapshot is taken from its url.
+    pub async fn capture<U: IntoUrl>(&self, url: U) -> Result<Archived> {
+        let target_url = url.into_url()?;
+        // the time stamps of snapshots only have a resolution of seconds
+        let started = Utc::now().trunc_subsecs(0);
+        let resp = self
+            .client
+            .get(&format!("{}/save/{}", WAYBACK_URL, target_url))
+            .await?;
+        if resp.is_success() {
+            if let Some(archived) = archived_from_save(&target_url, &resp) {
+                debug!(
+                    "Archived target url {} at {}",
+                    archived.target_url, archived.archived_url
+                );
+                return Ok(archived);
+            }
+        }
+        // the snapshot itself may contain a CAPTCHA, so this is only checked without one
+        if resp.is_rate_limited() {
+            error!("Save Page Now rate limited capturing {}", target_url);
+            return Err(Error::RateLimited(target_url.into()));
+        }
+        if !resp.is_success() {
+            error!(
+                "Save Page Now responded with {} for {}",
+                resp.status, target_url
+            );
+            return Err(Error::ServerError(target_url.into()));
+        }
+
+        match self.lookup(&target_url).await? {
+            Some(archived) if archived.time_stamp.is_some_and(|time| time >= started) => {
+                Ok(archived)
+            }
+            _ => {
+                error!("Failed to archive {}", target_url);
+                Err(Error::MissingUrl(target_url.into()))
+            }
+        }
+    }
+
+    /// Looks up the most recent snapshot of `url` in its Wayback Machine timemap.
+    ///
+    /// See [`crate::ArchiveClient::lookup`] for how the status of the response is handled.
+    pub async fn lookup<U: IntoUrl>(&self, url: U) -> Result<Option<Archived>> {
+        let target_url = url.into_url()?;
+        let resp = self
+            .client
+            .get(&format!("{}/web/timemap/link/{}", WAYBACK_URL, target_url))
+            .await?;
+        archived_from_timemap(target_url, Provider::Wayback, &resp)
+    }
+}
+
+/// Reads the snapshot from the `Content-Location` or `Location` header of a Save Page Now
+/// response.
+fn archived_from_save(target_url: &Url, resp: &transport::Response) -> Option<Archived> {
+    let location = resp
+        .header("Content-Location")
+        .or_else(|| resp.header("Location"))
+        .filter(|location| location.contains("/web/"))?;
+    let archived_url = if location.starts_with('/') {
+        format!("{}{}", WAYBACK_URL, location)
+    } else {
+        location.to_string()
+    };
+    Some(Archived {
+        target_url: target_url.to_string(),
+        time_stamp: parse::parse_wayback_timestamp(&archived_url),
+        archived_url,
+        submit_token: String::new(),
+        provider: Provider::Wayback,
+    })
+}
+
+impl Default for WaybackClient {
+    fn default() -> Self {
+        WaybackClient::new("archiveis-rs")
+    }
+}
+
+#[async_trait]
+impl Archiver for WaybackClient {
+    fn provider(&self) -> Provider {
+        Provider::Wayback
+    }
+
+    async fn capture(&self, url: &Url) -> Result<Archived> {
+        WaybackClient::capture(self, url).await
+    }
+
+    async fn lookup(&self, url: &Url) -> Result<Option<Archived>> {
+        WaybackClient::lookup(self, url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> transport::Response {
+        transport::Response {
+            status: 200,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn snapshot_location() {
+        let target: Url = "http://example.com/".parse().unwrap();
+        let resp = response(&[(
+            "content-location",
+            "/web/19941106084937/http://example.com/",
+        )]);
+        let archived = archived_from_save(&target, &resp).unwrap();
+        assert_eq!(
+            archived.archived_url,
+            "https://web.archive.org/web/19941106084937/http://example.com/"
+        );
+        assert_eq!(archived.time_stamp, "1994-11-06T08:49:37Z".parse().ok());
+
+        let resp = response(&[(
+            "location",
+            "https://web.archive.org/web/20200101000000/http://example.com/",
+        )]);
+        let archived = archived_from_save(&target, &resp).unwrap();
+        assert_eq!(
+            archived.archived_url,
+            "https://web.archive.org/web/20200101000000/http://example.com/"
+        );
+
+        assert!(archived_from_save(&target, &response(&[])).is_none());
+        assert!(archived_from_save(&target, &response(&[("location", "/save/")])).is_none());
+    }
+}